@@ -1,77 +1,260 @@
-use std::iter::Iterator;
-use std::string::ToString;
+use crate::addr_checker::patterns::{Pattern, PatternSet};
+use crate::addr_gen::user_friendly::{FriendlyFormat, USER_FRIENDLY_LEN, encode_user_friendly};
+use crate::addr_checker::score::{score_address, BeautyScore};
+use crate::addr_checker::config::{BeautyRule, RulesConfig};
+use crate::addr_checker::nibbles::{AddressHash, NibblePattern, ADDRESS_NIBBLES, encode_hex};
+
+pub mod patterns;
+pub mod expressions;
+pub mod nibbles;
+pub mod score;
+pub mod config;
+
+#[derive(Clone, Debug)]
+pub struct BeautyMatch {
+    pub rule: u8,
+    pub pattern: String,
+    pub score: Option<u32>,
+}
+
+impl BeautyMatch {
+    fn from_pattern(pattern: &Pattern, score: Option<u32>) -> Self {
+        Self { rule: pattern.rule, pattern: pattern.describe(), score }
+    }
+}
+
+impl Default for BeautyAddressCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct BeautyAddressCheck {
-    pub hex_letters: Vec<char>,
-    pub hex_numbers: Vec<char>,
-    pub hex_all_chars: Vec<char>,
     pub keywords: Vec<String>,
+    pub rules: Vec<BeautyRule>,
+    pub patterns: PatternSet,
+    pub min_score: Option<u32>,
+    pub friendly_format: FriendlyFormat,
+    pub workchain: i8,
+    nibble_patterns: Vec<NibblePattern>,
 }
 
 impl BeautyAddressCheck {
-    const PL: usize = 7;
+    pub const SCORE_RULE: u8 = 20;
     pub fn new() -> Self {
-        Self {
-            hex_letters: "abcdef".chars().collect(),
-            hex_numbers: "0123456789".chars().collect(),
-            hex_all_chars: "0123456789abcdef".chars().collect(),
-            keywords: vec![
-                "abcabca".to_string(),
-                "1234321".to_string(),
-                "0123456".to_string(),
-                "1234567".to_string(),
-                "2345678".to_string(),
-                "3456789".to_string(),
-                "4567890".to_string(),
-            ],
+        Self::from_config(&RulesConfig::default(), PatternSet::new()).unwrap()
+    }
+
+    pub fn from_config(config: &RulesConfig, patterns: PatternSet) -> Result<Self, String> {
+        let nibble_patterns = patterns.patterns.iter().map(NibblePattern::compile).collect();
+        Ok(Self {
+            keywords: config.keywords(),
+            rules: config.compile()?,
+            patterns,
+            min_score: None,
+            friendly_format: FriendlyFormat::default(),
+            workchain: 0,
+            nibble_patterns,
+        })
+    }
+
+    /// Scoring mode: instead of first-match rules every address gets a score, addresses with
+    /// at least `min_score` points are hits of `SCORE_RULE`.
+    pub fn with_scoring(mut self, min_score: u32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Bounceable/testnet flags for patterns over the user-friendly address.
+    pub fn with_friendly_format(mut self, format: FriendlyFormat) -> Self {
+        self.friendly_format = format;
+        self
+    }
+
+    pub fn with_workchain(mut self, workchain: i8) -> Self {
+        self.workchain = workchain;
+        self
+    }
+
+    pub fn score(&self, hash: &AddressHash) -> BeautyScore {
+        score_address(hash, &self.keywords)
+    }
+
+    /// Same as `check`, but works on the raw account hash and allocates only on a hit.
+    pub fn check_raw(&self, hash: &AddressHash) -> Option<BeautyMatch> {
+        let literal = self.nibble_patterns.iter()
+            .position(|p| p.matches(hash))
+            .map(|i| &self.patterns.patterns[i]);
+        let pattern = match literal {
+            Some(pattern) => Some(pattern),
+            None if !self.patterns.expressions.is_empty() => {
+                let mut buffer = [0u8; ADDRESS_NIBBLES];
+                self.rule_expressions(encode_hex(hash, &mut buffer))
+            }
+            None => None,
+        };
+        let pattern = pattern.or_else(|| self.rule_user_friendly(hash));
+
+        if let Some(min_score) = self.min_score {
+            let score = self.score(hash);
+            if let Some(pattern) = pattern {
+                return Some(BeautyMatch::from_pattern(pattern, Some(score.total)));
+            }
+            if score.total >= min_score {
+                return Some(BeautyMatch {
+                    rule: BeautyAddressCheck::SCORE_RULE,
+                    pattern: score.describe(),
+                    score: Some(score.total),
+                });
+            }
+            return None;
+        }
+
+        if let Some(pattern) = pattern {
+            return Some(BeautyMatch::from_pattern(pattern, None));
         }
+        self.first_rule(hash)
+            .map(|rule| BeautyMatch { rule: rule.id, pattern: rule.describe(hash), score: None })
+    }
+
+    fn first_rule(&self, hash: &AddressHash) -> Option<&BeautyRule> {
+        self.rules.iter().find(|r| r.matches(hash))
+    }
+
+    /// Id of the first configured rule matching the raw account hash, 0 if none.
+    pub fn rule_beauty_raw(&self, hash: &AddressHash) -> u8 {
+        self.first_rule(hash).map(|r| r.id).unwrap_or(0)
+    }
+
+    pub fn check(&self, address: &str) -> Option<BeautyMatch> {
+        self.check_raw(&decode_address(address)?)
+    }
+
+    pub fn rule_expressions(&self, address: &str) -> Option<&Pattern> {
+        self.patterns.expressions.find(address)
+    }
+
+    /// Patterns over base64url and base64 user-friendly forms, encoded only if there are any.
+    pub fn rule_user_friendly(&self, hash: &AddressHash) -> Option<&Pattern> {
+        let formats = [(&self.patterns.base64url, true), (&self.patterns.base64, false)];
+        for &(patterns, url_safe) in formats.iter() {
+            if patterns.is_empty() {
+                continue;
+            }
+            let mut buffer = [0u8; USER_FRIENDLY_LEN];
+            let format = self.friendly_format.with_url_safe(url_safe);
+            let address = encode_user_friendly(self.workchain, hash, format, &mut buffer);
+            if let Some(pattern) = patterns.find(address) {
+                return Some(pattern);
+            }
+        }
+        None
     }
 
-    #[allow(unused_doc_comments)]
     pub fn rule_beauty_address(&self, address: &str) -> u8 {
-        let address: String = address.into();
-        let mut char_vec: Vec<char> = address.chars().collect();
+        decode_address(address).map(|hash| self.rule_beauty_raw(&hash)).unwrap_or(0)
+    }
+}
+
+fn decode_address(address: &str) -> Option<AddressHash> {
+    let bytes = hex::decode(address).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&bytes);
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
 
-        /// check that in 8 chars only 2 or lower different unique chars
-        let mut chunk: Vec<char> = vec![];
-        for chunk_ in char_vec.chunks(8) {
-            chunk = chunk_.into();
+    /// String-based check from before `check_raw`, kept as the reference for the built-in rules.
+    fn legacy_rule(keywords: &[String], address: &str) -> u8 {
+        let mut chars: Vec<char> = address.chars().collect();
+        for chunk in chars.chunks(8) {
+            let mut chunk = chunk.to_vec();
             chunk.sort();
             chunk.dedup();
             if chunk.len() < 3 {
                 return 1;
             }
         }
-
-        let mut prefix: Vec<char> = char_vec.clone()[0..BeautyAddressCheck::PL].to_vec();
-
-        /// check for predefine keywords in prefix
-        let prefix2: String = prefix.clone().into_iter().collect();
-        if self.keywords.contains(&prefix2) {
+        let mut prefix = chars[0..7].to_vec();
+        if keywords.contains(&prefix.iter().collect()) {
             return 2;
         }
-
-        /// check for 111111 122121 prefixes
         prefix.sort();
         prefix.dedup();
         if prefix.len() < 3 {
             return 3;
         }
-
-        /// check that contains only 5 or lower different unique chars
-        char_vec.sort();
-        char_vec.dedup();
-        if char_vec.len() < 6 {
+        chars.sort();
+        chars.dedup();
+        if chars.len() < 6 {
             return 4;
         }
-
-        /// check for only contains chars or only numbers
-        let char_vec2 = char_vec.clone();
-        if !char_vec.into_iter().map(|x| self.hex_numbers.contains(&x)).collect::<Vec<bool>>().contains(&false) ||
-            !char_vec2.into_iter().map(|x| self.hex_letters.contains(&x)).collect::<Vec<bool>>().contains(&false) {
+        if chars.iter().all(|c| c.is_ascii_digit()) || chars.iter().all(|c| c.is_ascii_alphabetic()) {
             return 5;
         }
-
         0
     }
-}
\ No newline at end of file
+
+    /// Hashes over a few nibble values, so every built-in rule fires now and then.
+    fn hashes(count: usize) -> Vec<AddressHash> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..count).map(|_| {
+            let alphabet: Vec<u8> = (0..rng.gen_range(2, 17)).map(|_| rng.gen_range(0, 16)).collect();
+            let mut hash = [0u8; 32];
+            for byte in hash.iter_mut() {
+                let high = alphabet[rng.gen_range(0, alphabet.len())];
+                let low = alphabet[rng.gen_range(0, alphabet.len())];
+                *byte = high << 4 | low;
+            }
+            hash
+        }).collect()
+    }
+
+    #[test]
+    fn check_raw_agrees_with_string_rules() {
+        let bas = BeautyAddressCheck::new();
+        let mut fired = [0; 6];
+        let mut crafted: Vec<AddressHash> = [
+            "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+            "1234567890abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+            "1212112890abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ].iter().map(|a| decode_address(a).unwrap()).collect();
+        crafted.extend(hashes(20_000));
+        for hash in &crafted {
+            let expected = legacy_rule(&bas.keywords, &hex::encode(hash));
+            assert_eq!(bas.rule_beauty_raw(hash), expected, "{}", hex::encode(hash));
+            fired[expected as usize] += 1;
+        }
+        assert!(fired.iter().all(|&count| count > 0), "{:?}", fired);
+    }
+
+    /// `cargo test --release -- --ignored --nocapture check_raw_speed`
+    #[test]
+    #[ignore]
+    fn check_raw_speed() {
+        let bas = BeautyAddressCheck::new();
+        let mut rng = StdRng::seed_from_u64(4);
+        let hashes: Vec<AddressHash> = (0..1_000_000).map(|_| rng.gen()).collect();
+
+        let start = Instant::now();
+        let legacy = hashes.iter().filter(|h| legacy_rule(&bas.keywords, &hex::encode(h)) > 0).count();
+        let legacy_time = start.elapsed();
+        let start = Instant::now();
+        let raw = hashes.iter().filter(|h| bas.check_raw(h).is_some()).count();
+        let raw_time = start.elapsed();
+
+        assert_eq!(legacy, raw);
+        println!("string rules: {:.0} ns/address, check_raw: {:.0} ns/address",
+                 legacy_time.as_nanos() as f64 / hashes.len() as f64,
+                 raw_time.as_nanos() as f64 / hashes.len() as f64);
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::addr_checker::nibbles::{AddressHash, NibbleMask, ADDRESS_NIBBLES, DIGITS_MASK, LETTERS_MASK,
+                                   nibbles_set};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// some `chunk_size` chunk has at most `max_unique` different chars, `chunk_size` divides 64
+    ChunkUnique,
+    /// address starts with one of `keywords`
+    KeywordPrefix,
+    /// first `prefix_len` chars have at most `max_unique` different chars
+    PrefixUnique,
+    /// whole address has at most `max_unique` different chars
+    TotalUnique,
+    /// address contains only digits or only letters
+    Charset,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RuleConfig {
+    pub id: u8,
+    pub kind: RuleKind,
+    #[serde(default = "RuleConfig::default_enabled")]
+    pub enabled: bool,
+    pub label: Option<String>,
+    pub chunk_size: Option<usize>,
+    pub prefix_len: Option<usize>,
+    pub max_unique: Option<u32>,
+    pub keywords: Option<Vec<String>>,
+}
+
+impl RuleConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn new(id: u8, kind: RuleKind, label: &str) -> Self {
+        Self {
+            id,
+            kind,
+            enabled: true,
+            label: Some(label.to_string()),
+            chunk_size: None,
+            prefix_len: None,
+            max_unique: None,
+            keywords: None,
+        }
+    }
+}
+
+/// Beauty rules checked in order, the first enabled rule that fires gives the rule id.
+///
+/// ```toml
+/// [[rules]]
+/// id = 1
+/// kind = "chunk_unique"
+/// label = "chunk"
+/// chunk_size = 8
+/// max_unique = 2
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        let mut chunk = RuleConfig::new(1, RuleKind::ChunkUnique, "chunk");
+        chunk.chunk_size = Some(8);
+        chunk.max_unique = Some(2);
+        let mut keyword = RuleConfig::new(2, RuleKind::KeywordPrefix, "keyword");
+        keyword.keywords = Some(vec![
+            "abcabca".to_string(),
+            "1234321".to_string(),
+            "0123456".to_string(),
+            "1234567".to_string(),
+            "2345678".to_string(),
+            "3456789".to_string(),
+            "4567890".to_string(),
+        ]);
+        let mut prefix = RuleConfig::new(3, RuleKind::PrefixUnique, "prefix");
+        prefix.prefix_len = Some(7);
+        prefix.max_unique = Some(2);
+        let mut unique = RuleConfig::new(4, RuleKind::TotalUnique, "unique");
+        unique.max_unique = Some(5);
+        let charset = RuleConfig::new(5, RuleKind::Charset, "charset");
+        Self { rules: vec![chunk, keyword, prefix, unique, charset] }
+    }
+}
+
+impl RulesConfig {
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// `.json` files are parsed as JSON, everything else as TOML.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read rules file: {}", e))?;
+        let is_json = Path::new(path).extension().map(|e| e == "json").unwrap_or(false);
+        if is_json {
+            serde_json::from_str(&content).map_err(|e| format!("unable to parse rules json: {}", e))
+        } else {
+            toml::from_str(&content).map_err(|e| format!("unable to parse rules toml: {}", e))
+        }
+    }
+
+    pub fn keywords(&self) -> Vec<String> {
+        self.rules.iter()
+            .filter(|r| r.enabled && r.kind == RuleKind::KeywordPrefix)
+            .flat_map(|r| r.keywords.clone().unwrap_or_default())
+            .collect()
+    }
+
+    pub fn compile(&self) -> Result<Vec<BeautyRule>, String> {
+        self.rules.iter()
+            .filter(|r| r.enabled)
+            .map(BeautyRule::compile)
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+enum RuleCheck {
+    ChunkUnique { chunk_size: usize, max_unique: u32 },
+    KeywordPrefix { keywords: Vec<(String, NibbleMask)> },
+    PrefixUnique { prefix_len: usize, max_unique: u32 },
+    TotalUnique { max_unique: u32 },
+    Charset,
+}
+
+#[derive(Clone)]
+pub struct BeautyRule {
+    pub id: u8,
+    pub label: String,
+    check: RuleCheck,
+}
+
+impl BeautyRule {
+    fn compile(config: &RuleConfig) -> Result<Self, String> {
+        if config.id == 0 {
+            return Err("rule id 0 is reserved for \"no match\"".to_string());
+        }
+        let max_unique = || config.max_unique
+            .ok_or_else(|| format!("rule {}: \"max_unique\" is required", config.id));
+        let length = |name: &str, value: Option<usize>| match value {
+            Some(value) if value > 0 && value <= ADDRESS_NIBBLES => Ok(value),
+            Some(value) => Err(format!("rule {}: \"{}\" {} is out of 1..=64", config.id, name, value)),
+            None => Err(format!("rule {}: \"{}\" is required", config.id, name)),
+        };
+        let check = match config.kind {
+            RuleKind::ChunkUnique => {
+                // a short trailing chunk would make the rule fire on almost every address
+                let chunk_size = length("chunk_size", config.chunk_size)?;
+                if ADDRESS_NIBBLES % chunk_size != 0 {
+                    return Err(format!("rule {}: \"chunk_size\" {} doesn't divide 64", config.id, chunk_size));
+                }
+                RuleCheck::ChunkUnique { chunk_size, max_unique: max_unique()? }
+            }
+            RuleKind::KeywordPrefix => {
+                let mut keywords = vec![];
+                for keyword in config.keywords.clone().unwrap_or_default() {
+                    let keyword = keyword.to_lowercase();
+                    if keyword.is_empty() || keyword.len() > ADDRESS_NIBBLES
+                        || !keyword.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("rule {}: keyword \"{}\" is not a hex string of up to 64 chars",
+                                           config.id, keyword));
+                    }
+                    let mask = NibbleMask::at(&keyword, 0);
+                    keywords.push((keyword, mask));
+                }
+                RuleCheck::KeywordPrefix { keywords }
+            }
+            RuleKind::PrefixUnique => RuleCheck::PrefixUnique {
+                prefix_len: length("prefix_len", config.prefix_len)?,
+                max_unique: max_unique()?,
+            },
+            RuleKind::TotalUnique => RuleCheck::TotalUnique { max_unique: max_unique()? },
+            RuleKind::Charset => RuleCheck::Charset,
+        };
+        let label = config.label.clone().unwrap_or_else(|| format!("rule{}", config.id));
+        Ok(Self { id: config.id, label, check })
+    }
+
+    pub fn matches(&self, hash: &AddressHash) -> bool {
+        match &self.check {
+            RuleCheck::ChunkUnique { chunk_size, max_unique } => (0..ADDRESS_NIBBLES)
+                .step_by(*chunk_size)
+                .any(|from| nibbles_set(hash, from, from + chunk_size).count_ones() <= *max_unique),
+            RuleCheck::KeywordPrefix { keywords } => keywords.iter().any(|(_, m)| m.matches(hash)),
+            RuleCheck::PrefixUnique { prefix_len, max_unique } =>
+                nibbles_set(hash, 0, *prefix_len).count_ones() <= *max_unique,
+            RuleCheck::TotalUnique { max_unique } =>
+                nibbles_set(hash, 0, ADDRESS_NIBBLES).count_ones() <= *max_unique,
+            RuleCheck::Charset => {
+                let all = nibbles_set(hash, 0, ADDRESS_NIBBLES);
+                all & LETTERS_MASK == 0 || all & DIGITS_MASK == 0
+            }
+        }
+    }
+
+    pub fn describe(&self, hash: &AddressHash) -> String {
+        match &self.check {
+            RuleCheck::KeywordPrefix { keywords } => keywords.iter()
+                .find(|(_, m)| m.matches(hash))
+                .map(|(keyword, _)| format!("{}:{}", self.label, keyword))
+                .unwrap_or_else(|| self.label.clone()),
+            _ => self.label.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Amount of compiled rules.
+    fn compile_toml(rules: &str) -> Result<usize, String> {
+        let config: RulesConfig = toml::from_str(rules).map_err(|e| e.to_string())?;
+        config.compile().map(|rules| rules.len())
+    }
+
+    #[test]
+    fn example_file_is_the_default() {
+        let example = RulesConfig::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/rules.example.toml")).unwrap();
+        assert_eq!(example.rules, RulesConfig::default().rules);
+        assert_eq!(example.compile().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn json_rules() {
+        let path = std::env::temp_dir().join(format!("address_gen_{}_rules.json", std::process::id()));
+        fs::write(&path, r#"{"rules": [{"id": 7, "kind": "total_unique", "max_unique": 3, "enabled": false},
+                                       {"id": 8, "kind": "charset"}]}"#).unwrap();
+        let config = RulesConfig::from_file(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(config.rules.len(), 2);
+        assert!(!config.rules[0].enabled);
+        let rules = config.compile().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!((rules[0].id, rules[0].label.as_str()), (8, "rule8"));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let chunk = |size: usize| format!("[[rules]]\nid = 1\nkind = \"chunk_unique\"\nchunk_size = {}\nmax_unique = 2", size);
+        assert_eq!(compile_toml(&chunk(16)), Ok(1));
+        assert!(compile_toml(&chunk(0)).unwrap_err().contains("out of 1..=64"));
+        assert!(compile_toml(&chunk(65)).unwrap_err().contains("out of 1..=64"));
+        assert!(compile_toml(&chunk(7)).unwrap_err().contains("doesn't divide 64"));
+        assert!(compile_toml("[[rules]]\nid = 1\nkind = \"chunk_unique\"\nchunk_size = 8")
+            .unwrap_err().contains("\"max_unique\" is required"));
+        assert!(compile_toml("[[rules]]\nid = 1\nkind = \"prefix_unique\"\nmax_unique = 2")
+            .unwrap_err().contains("\"prefix_len\" is required"));
+        assert!(compile_toml("[[rules]]\nid = 0\nkind = \"charset\"").unwrap_err().contains("reserved"));
+        assert!(compile_toml("[[rules]]\nid = 1\nkind = \"keyword_prefix\"\nkeywords = [\"12g4\"]")
+            .unwrap_err().contains("not a hex string"));
+        assert!(compile_toml("[[rules]]\nid = 1\nkind = \"suffix_unique\"").unwrap_err().contains("unknown variant"));
+    }
+
+    #[test]
+    fn disabled_rules_are_skipped() {
+        let mut config = RulesConfig::default();
+        config.rules[1].enabled = false;
+        assert!(config.keywords().is_empty());
+        assert_eq!(config.compile().unwrap().iter().map(|r| r.id).collect::<Vec<u8>>(), vec![1, 3, 4, 5]);
+    }
+}
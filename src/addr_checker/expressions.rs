@@ -0,0 +1,94 @@
+use regex::{RegexSet, RegexSetBuilder};
+use crate::addr_checker::patterns::{Pattern, PatternKind};
+
+/// All regex and glob patterns compiled into one `RegexSet`, so the address is scanned once
+/// no matter how many expressions there are.
+#[derive(Clone, Default)]
+pub struct ExpressionSet {
+    pub patterns: Vec<Pattern>,
+    set: Option<RegexSet>,
+}
+
+impl ExpressionSet {
+    const SIZE_LIMIT: usize = 256 << 20;
+
+    pub fn new() -> Self {
+        Self { patterns: vec![], set: None }
+    }
+
+    pub fn compile(patterns: Vec<Pattern>) -> Result<Self, String> {
+        if patterns.is_empty() {
+            return Ok(Self::new());
+        }
+        let sources: Vec<String> = patterns.iter()
+            .map(|p| match p.kind {
+                PatternKind::Glob => glob_to_regex(&p.value),
+                _ => p.value.clone(),
+            })
+            .collect();
+        let set = RegexSetBuilder::new(&sources)
+            .size_limit(Self::SIZE_LIMIT)
+            .dfa_size_limit(Self::SIZE_LIMIT)
+            .build()
+            .map_err(|e| format!("unable to compile regex patterns: {}", e))?;
+        Ok(Self { patterns, set: Some(set) })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn find(&self, address: &str) -> Option<&Pattern> {
+        let set = self.set.as_ref()?;
+        if !set.is_match(address) {
+            return None;
+        }
+        set.matches(address).iter().next().map(|i| &self.patterns[i])
+    }
+}
+
+/// `*` matches any run of chars, `?` a single char, `[...]` a char class and `[!...]` a negated
+/// one; the glob must cover the whole address.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut in_class = false;
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            _ if in_class => regex.push(c),
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_classes() {
+        assert_eq!(glob_to_regex("a[bc]*"), "^a[bc].*$");
+        assert_eq!(glob_to_regex("a[!bc]?"), "^a[^bc].$");
+        assert_eq!(glob_to_regex("[a!]*"), "^[a!].*$");
+    }
+}
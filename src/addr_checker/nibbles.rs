@@ -0,0 +1,100 @@
+use crate::addr_checker::patterns::{Pattern, PatternKind};
+
+pub type AddressHash = [u8; 32];
+
+pub const ADDRESS_NIBBLES: usize = 64;
+pub const DIGITS_MASK: u16 = 0x03ff;
+pub const LETTERS_MASK: u16 = 0xfc00;
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+#[inline]
+pub fn nibble(hash: &AddressHash, index: usize) -> u8 {
+    let byte = hash[index / 2];
+    if index % 2 == 0 { byte >> 4 } else { byte & 0x0f }
+}
+
+/// Bit `n` is set if nibble `n` occurs in `hash[from..to]` (nibble indexes).
+#[inline]
+pub fn nibbles_set(hash: &AddressHash, from: usize, to: usize) -> u16 {
+    let mut set = 0u16;
+    for i in from..to {
+        set |= 1 << nibble(hash, i);
+    }
+    set
+}
+
+/// Hex encoding into a stack buffer, used when a string matcher has to see the address.
+pub fn encode_hex<'a>(hash: &AddressHash, buffer: &'a mut [u8; ADDRESS_NIBBLES]) -> &'a str {
+    for (i, byte) in hash.iter().enumerate() {
+        buffer[i * 2] = HEX_CHARS[(byte >> 4) as usize];
+        buffer[i * 2 + 1] = HEX_CHARS[(byte & 0x0f) as usize];
+    }
+    std::str::from_utf8(buffer).unwrap()
+}
+
+fn hex_value(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => 0,
+    }
+}
+
+/// Hex chars at fixed positions of the address as a mask/value pair over the raw hash.
+#[derive(Clone)]
+pub struct NibbleMask {
+    mask: AddressHash,
+    value: AddressHash,
+    first: usize,
+    last: usize,
+}
+
+impl NibbleMask {
+    pub fn at(hex: &str, offset: usize) -> Self {
+        let mut mask = [0u8; 32];
+        let mut value = [0u8; 32];
+        for (i, c) in hex.bytes().enumerate() {
+            let index = offset + i;
+            let shift = if index % 2 == 0 { 4 } else { 0 };
+            mask[index / 2] |= 0x0f << shift;
+            value[index / 2] |= hex_value(c) << shift;
+        }
+        let first = offset / 2;
+        let last = (offset + hex.len().max(1) - 1) / 2;
+        Self { mask, value, first, last }
+    }
+
+    #[inline]
+    pub fn matches(&self, hash: &AddressHash) -> bool {
+        (self.first..=self.last).all(|i| hash[i] & self.mask[i] == self.value[i])
+    }
+}
+
+/// Literal prefix/suffix/contains pattern compiled to masks: one for prefix and suffix,
+/// one per possible offset for contains.
+#[derive(Clone)]
+pub struct NibblePattern {
+    masks: Vec<NibbleMask>,
+}
+
+impl NibblePattern {
+    pub fn compile(pattern: &Pattern) -> Self {
+        let len = pattern.value.len();
+        let masks = match pattern.kind {
+            PatternKind::Prefix => vec![NibbleMask::at(&pattern.value, 0)],
+            PatternKind::Suffix => vec![NibbleMask::at(&pattern.value, ADDRESS_NIBBLES - len)],
+            PatternKind::Contains => (0..=ADDRESS_NIBBLES - len)
+                .map(|offset| NibbleMask::at(&pattern.value, offset))
+                .collect(),
+            PatternKind::Regex | PatternKind::Glob => vec![],
+        };
+        Self { masks }
+    }
+
+    #[inline]
+    pub fn matches(&self, hash: &AddressHash) -> bool {
+        self.masks.iter().any(|m| m.matches(hash))
+    }
+}
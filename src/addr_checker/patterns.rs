@@ -0,0 +1,354 @@
+use std::fs;
+use std::str::FromStr;
+use crate::addr_checker::expressions::ExpressionSet;
+use crate::addr_gen::user_friendly::USER_FRIENDLY_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatternKind {
+    Prefix,
+    Suffix,
+    Contains,
+    Regex,
+    Glob,
+}
+
+impl FromStr for PatternKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, String> {
+        match kind {
+            "prefix" => Ok(PatternKind::Prefix),
+            "suffix" => Ok(PatternKind::Suffix),
+            "contains" => Ok(PatternKind::Contains),
+            "regex" => Ok(PatternKind::Regex),
+            "glob" => Ok(PatternKind::Glob),
+            _ => Err(format!("unknown pattern kind \"{}\"", kind)),
+        }
+    }
+}
+
+impl PatternKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatternKind::Prefix => "prefix",
+            PatternKind::Suffix => "suffix",
+            PatternKind::Contains => "contains",
+            PatternKind::Regex => "regex",
+            PatternKind::Glob => "glob",
+        }
+    }
+
+    pub fn is_expression(&self) -> bool {
+        matches!(self, PatternKind::Regex | PatternKind::Glob)
+    }
+}
+
+/// Representation of the address a pattern is checked against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressFormat {
+    Hex,
+    Base64,
+    Base64Url,
+}
+
+impl FromStr for AddressFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, String> {
+        match format {
+            "hex" => Ok(AddressFormat::Hex),
+            "base64" => Ok(AddressFormat::Base64),
+            "base64url" => Ok(AddressFormat::Base64Url),
+            _ => Err(format!("unknown address format \"{}\"", format)),
+        }
+    }
+}
+
+impl AddressFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressFormat::Hex => "hex",
+            AddressFormat::Base64 => "base64",
+            AddressFormat::Base64Url => "base64url",
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        match self {
+            AddressFormat::Hex => 64,
+            AddressFormat::Base64 | AddressFormat::Base64Url => USER_FRIENDLY_LEN,
+        }
+    }
+
+    fn is_valid_char(&self, c: char) -> bool {
+        match self {
+            AddressFormat::Hex => c.is_ascii_hexdigit(),
+            AddressFormat::Base64 => c.is_ascii_alphanumeric() || c == '+' || c == '/',
+            AddressFormat::Base64Url => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub format: AddressFormat,
+    pub value: String,
+    pub rule: u8,
+}
+
+impl Pattern {
+    pub fn matches(&self, address: &str) -> bool {
+        match self.kind {
+            PatternKind::Prefix => address.starts_with(&self.value),
+            PatternKind::Suffix => address.ends_with(&self.value),
+            PatternKind::Contains => address.contains(&self.value),
+            PatternKind::Regex | PatternKind::Glob => false,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.format {
+            AddressFormat::Hex => format!("{}:{}", self.kind.as_str(), self.value),
+            format => format!("{}:{}:{}", self.kind.as_str(), format.as_str(), self.value),
+        }
+    }
+}
+
+/// Plain and regex/glob patterns over one user-friendly representation.
+#[derive(Clone, Default)]
+pub struct FormatPatterns {
+    pub literals: Vec<Pattern>,
+    pub expressions: ExpressionSet,
+}
+
+impl FormatPatterns {
+    fn compile(all: Vec<Pattern>) -> Result<Self, String> {
+        let (expressions, literals): (Vec<Pattern>, Vec<Pattern>) = all.into_iter()
+            .partition(|p| p.kind.is_expression());
+        Ok(Self { literals, expressions: ExpressionSet::compile(expressions)? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.literals.is_empty() && self.expressions.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.literals.len() + self.expressions.len()
+    }
+
+    pub fn find(&self, address: &str) -> Option<&Pattern> {
+        self.literals.iter().find(|p| p.matches(address))
+            .or_else(|| self.expressions.find(address))
+    }
+}
+
+/// Patterns loaded from a text file, one per line:
+///
+/// ```text
+/// # kind     value     [rule]
+/// prefix     0000000
+/// suffix     dead      12
+/// contains   c0ffee
+/// regex      ^(ab){4}
+/// glob       00*00     11
+/// regex:base64url  ^EQ.*TON$
+/// ```
+///
+/// A `#` at the line start or after whitespace starts a comment, so regexes may contain `#` but
+/// can't start with it. Rule defaults to `PatternSet::DEFAULT_RULE`.
+/// Kind may have a `:hex` (default), `:base64` or `:base64url` suffix to match the user-friendly
+/// address instead of the hex account id. Hex regex and glob patterns go to the `ExpressionSet`,
+/// plain hex ones are checked directly.
+#[derive(Clone, Default)]
+pub struct PatternSet {
+    pub patterns: Vec<Pattern>,
+    pub expressions: ExpressionSet,
+    pub base64: FormatPatterns,
+    pub base64url: FormatPatterns,
+}
+
+impl PatternSet {
+    pub const DEFAULT_RULE: u8 = 10;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_patterns(all: Vec<Pattern>) -> Result<Self, String> {
+        let mut hex = vec![];
+        let mut base64 = vec![];
+        let mut base64url = vec![];
+        for pattern in all {
+            match pattern.format {
+                AddressFormat::Hex => hex.push(pattern),
+                AddressFormat::Base64 => base64.push(pattern),
+                AddressFormat::Base64Url => base64url.push(pattern),
+            }
+        }
+        let hex = FormatPatterns::compile(hex)?;
+        Ok(Self {
+            patterns: hex.literals,
+            expressions: hex.expressions,
+            base64: FormatPatterns::compile(base64)?,
+            base64url: FormatPatterns::compile(base64url)?,
+        })
+    }
+
+    pub fn all(&self) -> Vec<Pattern> {
+        let mut all = self.patterns.clone();
+        all.extend(self.expressions.patterns.iter().cloned());
+        all.extend(self.base64.literals.iter().cloned());
+        all.extend(self.base64.expressions.patterns.iter().cloned());
+        all.extend(self.base64url.literals.iter().cloned());
+        all.extend(self.base64url.expressions.patterns.iter().cloned());
+        all
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read patterns file: {}", e))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut patterns = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = Self::strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let pattern = Self::parse_line(line)
+                .map_err(|e| format!("patterns file line {}: {}", number + 1, e))?;
+            patterns.push(pattern);
+        }
+        Self::from_patterns(patterns)
+    }
+
+    /// Cuts the line at a `#` which starts the line or follows whitespace.
+    fn strip_comment(line: &str) -> &str {
+        let mut previous = ' ';
+        for (i, c) in line.char_indices() {
+            if c == '#' && previous.is_whitespace() {
+                return &line[..i];
+            }
+            previous = c;
+        }
+        line
+    }
+
+    fn parse_line(line: &str) -> Result<Pattern, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err("expected \"<kind>[:format] <value> [rule]\"".to_string());
+        }
+        let mut kind_format = fields[0].splitn(2, ':');
+        let kind = kind_format.next().unwrap_or("").parse::<PatternKind>()?;
+        let format = kind_format.next().unwrap_or("hex").parse::<AddressFormat>()?;
+        let rule = Self::parse_rule(fields.get(2).copied())?;
+        Self::make_pattern(kind, format, fields[1], rule)
+    }
+
+    pub fn make_pattern(kind: PatternKind, format: AddressFormat, value: &str, rule: u8) -> Result<Pattern, String> {
+        if kind.is_expression() {
+            return Ok(Pattern { kind, format, value: value.to_string(), rule });
+        }
+        // hex account ids are lowercase, base64 is case sensitive
+        let value = match format {
+            AddressFormat::Hex => value.to_lowercase(),
+            _ => value.to_string(),
+        };
+        if value.len() > format.max_len() || !value.chars().all(|c| format.is_valid_char(c)) {
+            return Err(format!("\"{}\" is not a {} string of up to {} chars", value, format.as_str(), format.max_len()));
+        }
+        Ok(Pattern { kind, format, value, rule })
+    }
+
+    fn parse_rule(rule: Option<&str>) -> Result<u8, String> {
+        let rule = match rule {
+            Some(rule) => rule.parse::<u8>()
+                .map_err(|e| format!("unable to parse rule \"{}\": {}", rule, e))?,
+            None => Self::DEFAULT_RULE,
+        };
+        if rule == 0 {
+            return Err("rule 0 is reserved for \"no match\"".to_string());
+        }
+        Ok(rule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.expressions.is_empty()
+            && self.base64.is_empty() && self.base64url.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len() + self.expressions.len() + self.base64.len() + self.base64url.len()
+    }
+
+    pub fn find(&self, address: &str) -> Option<&Pattern> {
+        self.patterns.iter().find(|p| p.matches(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> Result<Pattern, String> {
+        let set = PatternSet::parse(line)?;
+        assert_eq!(set.len(), 1);
+        Ok(set.all().remove(0))
+    }
+
+    #[test]
+    fn every_kind_and_format() {
+        for kind in &["prefix", "suffix", "contains", "regex", "glob"] {
+            for (suffix, format) in &[("", AddressFormat::Hex), (":hex", AddressFormat::Hex),
+                                      (":base64", AddressFormat::Base64), (":base64url", AddressFormat::Base64Url)] {
+                let value = if *kind == "glob" { "ab*" } else { "ab" };
+                let pattern = parse_one(&format!("{}{} {} 12", kind, suffix, value)).unwrap();
+                assert_eq!(pattern.kind, kind.parse::<PatternKind>().unwrap());
+                assert_eq!((pattern.format, pattern.value.as_str(), pattern.rule), (*format, value, 12));
+            }
+        }
+    }
+
+    #[test]
+    fn values_and_rules() {
+        let pattern = parse_one("prefix DEAD").unwrap();
+        assert_eq!((pattern.value.as_str(), pattern.rule), ("dead", PatternSet::DEFAULT_RULE));
+        assert_eq!(pattern.describe(), "prefix:dead");
+        let pattern = parse_one("suffix:base64url Ton_-").unwrap();
+        assert_eq!(pattern.value, "Ton_-");
+        assert_eq!(pattern.describe(), "suffix:base64url:Ton_-");
+
+        assert!(parse_one("prefix xyz").is_err());
+        assert!(parse_one(&format!("prefix {}", "0".repeat(65))).is_err());
+        assert!(parse_one("prefix:base64 Ton_").is_err());
+        assert!(parse_one("prefix:base64url Ton+").is_err());
+        assert!(parse_one("prefix dead 0").is_err());
+        assert!(parse_one("prefix dead 256").is_err());
+        assert!(parse_one("prefix:base32 dead").is_err());
+        assert!(parse_one("infix dead").is_err());
+        assert!(parse_one("prefix").is_err());
+        assert!(parse_one("prefix dead 12 13").is_err());
+    }
+
+    #[test]
+    fn comments() {
+        let set = PatternSet::parse("# header\n\n  # indented\nprefix dead # comment\nsuffix beef\t#12\n").unwrap();
+        let values: Vec<(String, u8)> = set.all().into_iter().map(|p| (p.value, p.rule)).collect();
+        assert_eq!(values, vec![("dead".to_string(), 10), ("beef".to_string(), 10)]);
+
+        let pattern = parse_one("regex ^(ab)[^#]#{2} 11 # two hashes").unwrap();
+        assert_eq!((pattern.value.as_str(), pattern.rule), ("^(ab)[^#]#{2}", 11));
+        // a `#` inside a plain value is kept and rejected as a non-hex char
+        assert!(parse_one("prefix dead#beef").is_err());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = PatternSet::parse("prefix dead\nprefix xyz").err().unwrap();
+        assert!(error.starts_with("patterns file line 2:"), "{}", error);
+    }
+}
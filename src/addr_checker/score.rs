@@ -0,0 +1,219 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use crate::addr_checker::nibbles::{AddressHash, ADDRESS_NIBBLES, nibble};
+
+/// Points of every beauty criterion, unlike rule numbers all of them are evaluated. Scored for
+/// every candidate, so it lives on the stack and only `describe` allocates.
+#[derive(Clone, Debug, Default)]
+pub struct BeautyScore {
+    pub total: u32,
+    pub breakdown: [(&'static str, u32); 6],
+}
+
+impl BeautyScore {
+    pub fn describe(&self) -> String {
+        self.breakdown.iter()
+            .map(|(name, points)| format!("{}={}", name, points))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+pub fn score_address(hash: &AddressHash, keywords: &[String]) -> BeautyScore {
+    let mut chars = [0u8; ADDRESS_NIBBLES];
+    for (i, c) in chars.iter_mut().enumerate() {
+        *c = nibble(hash, i);
+    }
+
+    // longest run of the same char: 3 chars give 4 points, every next one 4 more
+    let run = longest_run(&chars) as u32;
+
+    // aligned 4-char chunks which occur more than once
+    let mut chunks = [0u16; ADDRESS_NIBBLES / 4];
+    for (i, chunk) in chunks.iter_mut().enumerate() {
+        *chunk = u16::from(hash[2 * i]) << 8 | u16::from(hash[2 * i + 1]);
+    }
+    let repeated = (0..chunks.len())
+        .filter(|&i| chunks.iter().enumerate().any(|(j, c)| i != j && *c == chunks[i]))
+        .count() as u32;
+
+    // longest palindrome anywhere in the address, random ones are 3-4 chars long
+    let palindrome = longest_palindrome(&chars) as u32;
+
+    // how many leading chars match one of the keywords
+    let keyword = keywords.iter()
+        .map(|k| k.bytes().zip(chars.iter()).take_while(|(k, c)| hex_value(*k) == **c).count())
+        .max()
+        .unwrap_or(0) as u32;
+
+    // missing hex chars, random addresses contain almost all 16
+    let unique = chars.iter().fold(0u16, |set, c| set | 1 << c).count_ones();
+
+    // letter/digit purity over expected 40 digits and 24 letters
+    let digits = chars.iter().filter(|&&c| c < 10).count() as u32;
+    let letters = ADDRESS_NIBBLES as u32 - digits;
+
+    let breakdown = [
+        ("run", if run >= 3 { (run - 2) * 4 } else { 0 }),
+        ("chunks", repeated * 3),
+        ("palindrome", if palindrome >= 5 { (palindrome - 4) * 3 } else { 0 }),
+        ("keyword", if keyword >= 4 { keyword * 2 } else { 0 }),
+        ("unique", (16 - unique).pow(2)),
+        ("purity", digits.saturating_sub(48).max(letters.saturating_sub(36)) * 2),
+    ];
+    BeautyScore { total: breakdown.iter().map(|(_, points)| points).sum(), breakdown }
+}
+
+fn hex_value(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => 0xff,
+    }
+}
+
+fn longest_run(chars: &[u8]) -> usize {
+    let mut longest = 1;
+    let mut current = 1;
+    for pair in chars.windows(2) {
+        if pair[0] == pair[1] {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 1;
+        }
+    }
+    longest
+}
+
+fn longest_palindrome(chars: &[u8]) -> usize {
+    let mut longest = 1;
+    for center in 0..chars.len() {
+        for &(mut left, mut right) in &[(center as isize, center as isize), (center as isize, center as isize + 1)] {
+            while left >= 0 && (right as usize) < chars.len() && chars[left as usize] == chars[right as usize] {
+                left -= 1;
+                right += 1;
+            }
+            longest = longest.max((right - left - 1) as usize);
+        }
+    }
+    longest
+}
+
+/// Keeps the best `size` scores seen by all threads, `offer` tells whether a hit made it in.
+pub struct Leaderboard {
+    size: usize,
+    scores: Mutex<BinaryHeap<Reverse<u32>>>,
+}
+
+impl Leaderboard {
+    pub fn new(size: usize) -> Self {
+        Self { size, scores: Mutex::new(BinaryHeap::with_capacity(size + 1)) }
+    }
+
+    pub fn offer(&self, score: u32) -> bool {
+        let mut scores = self.scores.lock().unwrap();
+        if scores.len() < self.size {
+            scores.push(Reverse(score));
+            return true;
+        }
+        match scores.peek() {
+            Some(Reverse(lowest)) if score > *lowest => {
+                scores.pop();
+                scores.push(Reverse(score));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scores zero on every criterion.
+    const PLAIN: &str = "6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b";
+
+    fn score(address: &str, keywords: &[&str]) -> BeautyScore {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hex::decode(address).unwrap());
+        let keywords: Vec<String> = keywords.iter().map(|k| k.to_string()).collect();
+        score_address(&hash, &keywords)
+    }
+
+    fn points(score: &BeautyScore, criterion: &str) -> u32 {
+        score.breakdown.iter().find(|(name, _)| *name == criterion).unwrap().1
+    }
+
+    /// `PLAIN` with `segment` written at nibble `at`.
+    fn with(at: usize, segment: &str) -> String {
+        format!("{}{}{}", &PLAIN[..at], segment, &PLAIN[at + segment.len()..])
+    }
+
+    #[test]
+    fn plain_address() {
+        let score = score(PLAIN, &["abcdef1234"]);
+        assert_eq!(score.total, 0, "{}", score.describe());
+        assert_eq!(score.describe(), "run=0,chunks=0,palindrome=0,keyword=0,unique=0,purity=0");
+    }
+
+    #[test]
+    fn run() {
+        assert_eq!(points(&score(&with(20, "777"), &[]), "run"), 4);
+        assert_eq!(points(&score(&with(20, "7777777"), &[]), "run"), 20);
+    }
+
+    #[test]
+    fn chunks() {
+        let repeated = score(&with(8, "c0de").replace("a4eaa22f", "a4eac0de"), &[]);
+        assert_eq!(repeated.total, 6, "{}", repeated.describe());
+        // the same chars off the 4-char grid don't count
+        let shifted = score(&with(8, "c0de").replace("a4eaa22f", "a4ec0dea"), &[]);
+        assert_eq!(points(&shifted, "chunks"), 0);
+    }
+
+    #[test]
+    fn palindrome() {
+        let score = score(&with(30, "1234321"), &[]);
+        assert_eq!(score.total, 9, "{}", score.describe());
+    }
+
+    #[test]
+    fn keyword() {
+        let keywords = ["abcdef1234", "abc0"];
+        assert_eq!(score(&with(0, "abcdef1"), &keywords).total, 14);
+        assert_eq!(points(&score(&with(0, "abc"), &keywords), "keyword"), 0);
+        assert_eq!(points(&score(&with(0, "abcdef1"), &[]), "keyword"), 0);
+    }
+
+    #[test]
+    fn unique_chars() {
+        let score = score(&"1248".repeat(16), &[]);
+        assert_eq!(points(&score, "unique"), 144);
+        assert_eq!(score.total, score.breakdown.iter().map(|(_, points)| points).sum::<u32>());
+    }
+
+    #[test]
+    fn purity() {
+        let digits = "1234567890".repeat(7);
+        assert_eq!(points(&score(&digits[..64], &[]), "purity"), 32);
+        let letters = "abcdef".repeat(11);
+        assert_eq!(points(&score(&letters[..64], &[]), "purity"), 56);
+    }
+
+    #[test]
+    fn leaderboard_keeps_best_scores() {
+        let leaderboard = Leaderboard::new(2);
+        assert!(leaderboard.offer(5));
+        assert!(leaderboard.offer(3));
+        assert!(leaderboard.offer(4));
+        assert!(!leaderboard.offer(3));
+        // a tie with the lowest kept score doesn't get in
+        assert!(!leaderboard.offer(4));
+        assert!(leaderboard.offer(6));
+        assert!(!leaderboard.offer(4));
+        assert!(!Leaderboard::new(0).offer(100));
+    }
+}
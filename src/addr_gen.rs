@@ -1,62 +1,206 @@
 use ton_sdk::{ContractImage};
 use std::fs::OpenOptions;
-use ed25519_dalek::Keypair;
-use rand::rngs::ThreadRng;
+use std::io::Cursor;
+use curve25519_dalek::scalar::Scalar;
+use ed25519_dalek::{ExpandedSecretKey, Keypair, PublicKey, SecretKey};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use bip39::{MnemonicType, Language, Mnemonic};
 use crate::addr_gen::hdkey::{HDPrivateKey, KeyPair, sign_keypair_from_secret_key};
+use crate::addr_checker::nibbles::AddressHash;
+use crate::addr_gen::user_friendly::{FriendlyFormat, user_friendly};
+use crate::addr_gen::split_key::SplitKey;
+use crate::addr_gen::incremental::IncrementalKeys;
+use crate::addr_gen::state_init_hasher::StateInitHasher;
 
 mod hdkey;
+pub mod user_friendly;
+pub mod split_key;
+pub mod incremental;
+pub mod state_init_hasher;
 
-const HD_PATH: &str = "m/44'/396'/0'/0/0";
+pub const HD_PATH: &str = "m/44'/396'/0'/0/0";
 
+pub enum AccountSecret {
+    /// ed25519 secret key, ready to be used by a wallet.
+    Key(SecretKey),
+    /// Split-key mode: scalar to add to the customer secret, see `split_key::combine`.
+    Tweak(Scalar),
+    /// Incremental mode: key `index` of the stream from `base`, saved as a 64 bytes expanded key.
+    Incremental { base: [u8; 32], index: u64 },
+}
 
 pub struct Account {
     pub account_id: String,
-    pub keypair: Keypair,
+    pub workchain: i8,
+    pub hash: AddressHash,
+    pub public: PublicKey,
+    pub secret: AccountSecret,
     pub seed: String,
-    pub tvc: u8,
+    pub contract_hash: String,
 }
 
 impl Account {
+    /// Full raw address `workchain:account_id`, e.g. `0:3f2a...` or `-1:3f2a...`.
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.workchain, self.account_id)
+    }
     pub fn public_as_string(&self) -> String {
-        hex::encode(self.keypair.public.as_bytes())
+        hex::encode(self.public.as_bytes())
     }
+    /// Hex secret key, or `tweak:<hex>` in split-key mode.
     pub fn secret_as_string(&self) -> String {
-        hex::encode(self.keypair.secret.as_bytes())
+        match &self.secret {
+            AccountSecret::Key(secret) => hex::encode(secret.as_bytes()),
+            AccountSecret::Tweak(tweak) => format!("tweak:{}", hex::encode(tweak.as_bytes())),
+            AccountSecret::Incremental { base, index } =>
+                hex::encode(&IncrementalKeys::expanded_secret(base, *index)[..]),
+        }
+    }
+    pub fn user_friendly(&self, format: FriendlyFormat) -> String {
+        user_friendly(self.workchain, &self.hash, format)
     }
+    /// HD derivation path of the keypair, only for accounts generated from a mnemonic.
+    pub fn derivation_path(&self) -> Option<&'static str> {
+        if self.seed.is_empty() { None } else { Some(HD_PATH) }
+    }
+}
+
+/// Generated keys with the raw account hash, turned into an `Account` only when it is a hit.
+pub struct Candidate {
+    pub hash: AddressHash,
+    pub public: [u8; 32],
+    pub secret: AccountSecret,
+    pub seed: String,
 }
 
 pub struct AccountGenerator {
     pub contract_image: ContractImage,
-    pub csprng: ThreadRng,
-    pub tvc: u8
+    /// Seeded from the OS, or from `--seed` in deterministic mode.
+    pub csprng: ChaCha20Rng,
+    pub workchain: i8,
+    /// Hex representation hash of the contract code cell.
+    pub contract_hash: String,
+    pub split_key: Option<SplitKey>,
+    pub incremental: Option<IncrementalKeys>,
+    /// Fast account id hashing, `None` if the contract layout is not supported.
+    pub hasher: Option<StateInitHasher>,
 }
 
 
 impl AccountGenerator {
-    pub fn from_tvc_file(path: &str) -> Result<Self, String> {
+    const HASHER_CHECKS: usize = 16;
+
+    pub fn from_tvc_file(path: &str, workchain: i8) -> Result<Self, String> {
         let mut state_init = OpenOptions::new().read(true).open(path)
             .map_err(|e| format!("unable to open contract file: {}", e))?;
 
         let contract_image = ton_sdk::ContractImage::from_state_init(&mut state_init)
             .map_err(|e| format!("unable to load contract image: {}", e))?;
-        let csprng = rand::thread_rng();
+        let contract_hash = Self::code_hash(&contract_image)?;
+        let csprng = ChaCha20Rng::from_entropy();
+
+        let mut generator = Self {
+            contract_image,
+            csprng,
+            workchain,
+            contract_hash,
+            split_key: None,
+            incremental: None,
+            hasher: None,
+        };
+        match generator.fast_hasher() {
+            Ok(hasher) => generator.hasher = Some(hasher),
+            Err(e) => println!("Fast state init hashing is off: {}", e),
+        }
+        Ok(generator)
+    }
+
+    /// Builds the fast hasher and checks it against `ContractImage::account_id` on random keys.
+    fn fast_hasher(&mut self) -> Result<StateInitHasher, String> {
+        let marker = self.generate_keyair();
+        let hasher = StateInitHasher::new(&mut self.contract_image, &marker.public)?;
+        for _ in 0..Self::HASHER_CHECKS {
+            let public = self.generate_keyair().public;
+            if hasher.hash(public.as_bytes()) != self.image_address_hash(&public) {
+                return Err("account id doesn't match the contract image".to_string());
+            }
+        }
+        Ok(hasher)
+    }
+
+    /// Deterministic mode: every key, tweak, base and mnemonic comes from `seed`, `stream` keeps
+    /// threads apart.
+    pub fn with_seed(mut self, seed: u64, stream: u64) -> Self {
+        self.csprng = Self::seeded_rng(seed, stream);
+        self
+    }
+
+    pub fn seeded_rng(seed: u64, stream: u64) -> ChaCha20Rng {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        rng.set_stream(stream);
+        rng
+    }
+
+    pub fn with_split_key(mut self, split_key: SplitKey) -> Self {
+        self.split_key = Some(split_key);
+        self
+    }
+
+    pub fn with_incremental(mut self) -> Self {
+        self.incremental = Some(IncrementalKeys::new(&mut self.csprng));
+        self
+    }
 
-        Ok(Self { contract_image, csprng, tvc: 1})
+    /// Incremental mode continuing the stream of `base` from `index`, e.g. from a checkpoint.
+    pub fn with_incremental_from(mut self, base: [u8; 32], index: u64) -> Self {
+        self.incremental = Some(IncrementalKeys::from_base(base, index));
+        self
+    }
+
+    fn code_hash(contract_image: &ContractImage) -> Result<String, String> {
+        let code = contract_image.get_serialized_code()
+            .map_err(|e| format!("unable to serialize contract code: {}", e))?;
+        let cell = ton_types::cells_serialization::deserialize_tree_of_cells(&mut Cursor::new(code))
+            .map_err(|e| format!("unable to deserialize contract code: {}", e))?;
+        Ok(cell.repr_hash().to_hex_string())
     }
 
     pub fn generate_keyair(&mut self) -> Keypair {
         Keypair::generate(&mut self.csprng)
     }
 
-    fn generate_address(&self) -> String {
-        let mut addr = hex::encode(self.contract_image.account_id().cell().cell_data().data());
-        addr.truncate(64);
-        addr
+    fn generate_address_hash(&self) -> AddressHash {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&self.contract_image.account_id().cell().cell_data().data()[..32]);
+        hash
+    }
+
+    #[allow(unused_must_use)]
+    fn image_address_hash(&mut self, public: &PublicKey) -> AddressHash {
+        self.contract_image.set_public_key(public);
+        self.generate_address_hash()
+    }
+
+    fn address_hash(&mut self, public: &[u8; 32]) -> AddressHash {
+        match &self.hasher {
+            Some(hasher) => hasher.hash(public),
+            // keys of candidates are always valid points
+            None => self.image_address_hash(&PublicKey::from_bytes(public).unwrap()),
+        }
     }
 
     fn generate_account(&self, keypair: Keypair) -> Account {
-        self::Account { account_id: self.generate_address(), keypair, seed: String::new(), tvc: self.tvc}
+        let hash = self.generate_address_hash();
+        self::Account {
+            account_id: hex::encode(hash),
+            workchain: self.workchain,
+            hash,
+            public: keypair.public,
+            secret: AccountSecret::Key(keypair.secret),
+            seed: String::new(),
+            contract_hash: self.contract_hash.clone(),
+        }
     }
 
     #[allow(unused_must_use)]
@@ -69,23 +213,86 @@ impl AccountGenerator {
     pub fn generate_random_account(&mut self) -> Account {
         let keypair = self.generate_keyair();
         self.contract_image.set_public_key(&keypair.public);
-        self::Account { account_id: self.generate_address(), keypair, seed: String::new(), tvc: self.tvc }
+        self.generate_account(keypair)
     }
 
 
-    #[allow(unused_must_use)]
     pub fn generate_account_from_random_seed(&mut self) -> Account {
-        let mnemonic = Mnemonic::new(MnemonicType::Words12, Language::English);
+        let candidate = self.candidate_from_random_seed();
+        self.account_from_candidate(candidate)
+    }
+
+    /// Random keypair, a random tweak of the customer key in split-key mode or the next key of
+    /// the stream in incremental mode.
+    pub fn random_candidate(&mut self) -> Candidate {
+        let (public, secret) = if let Some(keys) = self.incremental.as_mut() {
+            let (index, public) = keys.next();
+            (public, AccountSecret::Incremental { base: keys.base(), index })
+        } else if let Some(split_key) = self.split_key {
+            let (tweak, public) = split_key.random_tweak(&mut self.csprng);
+            (public, AccountSecret::Tweak(tweak))
+        } else {
+            let keypair = self.generate_keyair();
+            (keypair.public.to_bytes(), AccountSecret::Key(keypair.secret))
+        };
+        Candidate { hash: self.address_hash(&public), public, secret, seed: String::new() }
+    }
+
+    pub fn candidate_from_random_seed(&mut self) -> Candidate {
+        let mut entropy = vec![0u8; MnemonicType::Words12.entropy_bits() / 8];
+        self.csprng.fill_bytes(&mut entropy);
+        // 128 bits is a valid entropy size
+        let mnemonic = Mnemonic::from_entropy(&entropy, Language::English).unwrap();
         let seed: String = mnemonic.phrase().into();
         let hdk = HDPrivateKey::from_mnemonic(&seed)
             .derive_path(&HD_PATH.to_string(), false);
 
         let keypair: KeyPair = sign_keypair_from_secret_key(hdk.secret());
         let keypair = keypair.decode();
-        self.contract_image.set_public_key(&keypair.public);
-        let account_id= self.generate_address();
-        self::Account { account_id, keypair, seed , tvc: self.tvc}
+        let public = keypair.public.to_bytes();
+        Candidate {
+            hash: self.address_hash(&public),
+            public,
+            secret: AccountSecret::Key(keypair.secret),
+            seed,
+        }
+    }
+
+    /// Raw `workchain:account_id` address of the contract for a public key.
+    pub fn address_of(&mut self, public: &PublicKey) -> String {
+        format!("{}:{}", self.workchain, hex::encode(self.image_address_hash(public)))
+    }
+
+    /// Checks a hit found with the fast hasher against `ContractImage::account_id`, and rebuilds
+    /// an incremental key from its base and index to check it gives the same public key.
+    pub fn verify_candidate(&mut self, candidate: &Candidate) -> Result<(), String> {
+        let public = PublicKey::from_bytes(&candidate.public)
+            .map_err(|e| format!("invalid public key of a candidate: {}", e))?;
+        if let AccountSecret::Incremental { base, index } = &candidate.secret {
+            let expanded = IncrementalKeys::expanded_secret(base, *index);
+            let secret = ExpandedSecretKey::from_bytes(&expanded)
+                .map_err(|e| format!("unable to rebuild incremental key {}: {}", index, e))?;
+            if PublicKey::from(&secret) != public {
+                return Err(format!("incremental key {} doesn't match its public key", index));
+            }
+        }
+        if self.hasher.is_some() && self.image_address_hash(&public) != candidate.hash {
+            return Err(format!("fast hash of {} doesn't match the contract image", hex::encode(candidate.hash)));
+        }
+        Ok(())
+    }
 
+    pub fn account_from_candidate(&self, candidate: Candidate) -> Account {
+        self::Account {
+            account_id: hex::encode(candidate.hash),
+            workchain: self.workchain,
+            hash: candidate.hash,
+            // candidate keys are always valid points
+            public: PublicKey::from_bytes(&candidate.public).unwrap(),
+            secret: candidate.secret,
+            seed: candidate.seed,
+            contract_hash: self.contract_hash.clone(),
+        }
     }
 }
 
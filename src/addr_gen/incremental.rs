@@ -0,0 +1,413 @@
+use rand::RngCore;
+use sha2::{Digest, Sha512};
+
+/// Incremental key search: one random clamped scalar `s`, then public keys of `s + 8`, `s + 16`, ...
+/// by adding `8*G` to the point. Stepping by 8 keeps the 3 low bits clear, and `s` starts below
+/// `2^254 + 2^253` with bit 254 set, so every scalar in the stream is a valid clamped ed25519 scalar.
+/// Points are kept in extended coordinates and normalized to affine in batches, with one field
+/// inversion per batch.
+pub struct IncrementalKeys {
+    base: [u8; 32],
+    next_index: u64,
+    point: Point,
+    step: Point,
+    batch: Vec<[u8; 32]>,
+    batch_start: u64,
+    position: usize,
+}
+
+impl IncrementalKeys {
+    pub const BATCH: usize = 256;
+
+    pub fn new<R: RngCore>(rng: &mut R) -> Self {
+        Self::from_base(Self::random_base(rng), 0)
+    }
+
+    /// Random clamped scalar in `[2^254, 2^254 + 2^253)`.
+    pub fn random_base<R: RngCore>(rng: &mut R) -> [u8; 32] {
+        let mut base = [0u8; 32];
+        rng.fill_bytes(&mut base);
+        base[0] &= 248;
+        base[31] &= 31;
+        base[31] |= 64;
+        base
+    }
+
+    /// Stream starting at `base + 8 * index`.
+    pub fn from_base(base: [u8; 32], index: u64) -> Self {
+        let point = Point::basepoint().mul(&add_multiple_of_8(&base, index));
+        let step = Point::basepoint().mul(&add_multiple_of_8(&[0u8; 32], 1));
+        Self { base, next_index: index, point, step, batch: vec![], batch_start: index, position: 0 }
+    }
+
+    pub fn base(&self) -> [u8; 32] {
+        self.base
+    }
+
+    /// Index and compressed public key of the next scalar.
+    pub fn next(&mut self) -> (u64, [u8; 32]) {
+        if self.position == self.batch.len() {
+            self.fill_batch();
+        }
+        let index = self.batch_start + self.position as u64;
+        let public = self.batch[self.position];
+        self.position += 1;
+        (index, public)
+    }
+
+    fn fill_batch(&mut self) {
+        let mut points = Vec::with_capacity(Self::BATCH);
+        for _ in 0..Self::BATCH {
+            points.push(self.point);
+            self.point = self.point.add(&self.step);
+        }
+        // Montgomery's trick: invert all Z with one inversion
+        let mut products = Vec::with_capacity(Self::BATCH);
+        let mut product = Fe::ONE;
+        for point in &points {
+            products.push(product);
+            product = product.mul(&point.z);
+        }
+        let mut inverse = product.invert();
+        self.batch.clear();
+        self.batch.resize(Self::BATCH, [0u8; 32]);
+        for i in (0..Self::BATCH).rev() {
+            let z_inverse = inverse.mul(&products[i]);
+            inverse = inverse.mul(&points[i].z);
+            self.batch[i] = compress(&points[i].x.mul(&z_inverse), &points[i].y.mul(&z_inverse));
+        }
+        self.batch_start = self.next_index;
+        self.next_index += Self::BATCH as u64;
+        self.position = 0;
+    }
+
+    /// Expanded ed25519 secret `scalar || nonce` of the key at `index`.
+    pub fn expanded_secret(base: &[u8; 32], index: u64) -> [u8; 64] {
+        let scalar = add_multiple_of_8(base, index);
+        let mut hash = Sha512::new();
+        hash.input(b"address_gen incremental nonce");
+        hash.input(&scalar);
+        let mut expanded = [0u8; 64];
+        expanded[..32].copy_from_slice(&scalar);
+        expanded[32..].copy_from_slice(&hash.result()[..32]);
+        expanded
+    }
+}
+
+/// `value + 8 * index` over 256 bits little endian.
+fn add_multiple_of_8(value: &[u8; 32], index: u64) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    let mut carry = (index as u128) << 3;
+    for i in 0..32 {
+        let sum = value[i] as u128 + (carry & 0xff);
+        result[i] = sum as u8;
+        carry = (carry >> 8) + (sum >> 8);
+    }
+    result
+}
+
+fn compress(x: &Fe, y: &Fe) -> [u8; 32] {
+    let mut bytes = y.to_bytes();
+    bytes[31] |= (x.to_bytes()[0] & 1) << 7;
+    bytes
+}
+
+/// Point in extended twisted Edwards coordinates, `x = X/Z, y = Y/Z, x*y = T/Z`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    /// `2d` of edwards25519, used by `add`.
+    const D2: Fe = Fe([1859910466990425, 932731440258426, 1072319116312658, 1815898335770999, 633789495995903]);
+    // little endian coordinates of the basepoint
+    const BASE_X: [u8; 32] = [
+        0x1a, 0xd5, 0x25, 0x8f, 0x60, 0x2d, 0x56, 0xc9, 0xb2, 0xa7, 0x25, 0x95, 0x60, 0xc7, 0x2c, 0x69,
+        0x5c, 0xdc, 0xd6, 0xfd, 0x31, 0xe2, 0xa4, 0xc0, 0xfe, 0x53, 0x6e, 0xcd, 0xd3, 0x36, 0x69, 0x21,
+    ];
+    const BASE_Y: [u8; 32] = [
+        0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+        0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    ];
+
+    fn identity() -> Self {
+        Self { x: Fe::ZERO, y: Fe::ONE, z: Fe::ONE, t: Fe::ZERO }
+    }
+
+    fn basepoint() -> Self {
+        let x = Fe::from_bytes(&Self::BASE_X);
+        let y = Fe::from_bytes(&Self::BASE_Y);
+        Self { x, y, z: Fe::ONE, t: x.mul(&y) }
+    }
+
+    /// Unified addition (add-2008-hwcd-3), complete on edwards25519 so it doubles too.
+    fn add(&self, other: &Point) -> Point {
+        let a = self.y.sub(&self.x).mul(&other.y.sub(&other.x));
+        let b = self.y.add(&self.x).mul(&other.y.add(&other.x));
+        let c = self.t.mul(&Self::D2).mul(&other.t);
+        let d = self.z.add(&self.z).mul(&other.z);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    /// Double-and-add over a little endian scalar, only used to set up a stream.
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut result = Point::identity();
+        for i in (0..256).rev() {
+            result = result.add(&result);
+            if (scalar[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result.add(self);
+            }
+        }
+        result
+    }
+}
+
+/// Field element of GF(2^255 - 19) in five 51-bit limbs.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const LOW_51_BITS: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0, 0, 0, 0, 0]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |i: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & LOW_51_BITS,
+            (load(6) >> 3) & LOW_51_BITS,
+            (load(12) >> 6) & LOW_51_BITS,
+            (load(19) >> 1) & LOW_51_BITS,
+            (load(24) >> 12) & LOW_51_BITS,
+        ])
+    }
+
+    fn to_bytes(&self) -> [u8; 32] {
+        let mut limbs = Fe::reduce(self.0).0;
+        // subtract p if the value is at least p: q is 1 exactly when value + 19 overflows 2^255
+        let mut q = (limbs[0] + 19) >> 51;
+        q = (limbs[1] + q) >> 51;
+        q = (limbs[2] + q) >> 51;
+        q = (limbs[3] + q) >> 51;
+        q = (limbs[4] + q) >> 51;
+        limbs[0] += 19 * q;
+        limbs[1] += limbs[0] >> 51;
+        limbs[0] &= LOW_51_BITS;
+        limbs[2] += limbs[1] >> 51;
+        limbs[1] &= LOW_51_BITS;
+        limbs[3] += limbs[2] >> 51;
+        limbs[2] &= LOW_51_BITS;
+        limbs[4] += limbs[3] >> 51;
+        limbs[3] &= LOW_51_BITS;
+        limbs[4] &= LOW_51_BITS;
+
+        let mut value = [0u8; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut position = 0;
+        for limb in limbs.iter() {
+            acc |= (*limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 && position < 32 {
+                value[position] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                position += 1;
+            }
+        }
+        if position < 32 {
+            value[position] = acc as u8;
+        }
+        value
+    }
+
+    fn reduce(mut limbs: [u64; 5]) -> Fe {
+        let c0 = limbs[0] >> 51;
+        let c1 = limbs[1] >> 51;
+        let c2 = limbs[2] >> 51;
+        let c3 = limbs[3] >> 51;
+        let c4 = limbs[4] >> 51;
+        limbs[0] &= LOW_51_BITS;
+        limbs[1] &= LOW_51_BITS;
+        limbs[2] &= LOW_51_BITS;
+        limbs[3] &= LOW_51_BITS;
+        limbs[4] &= LOW_51_BITS;
+        limbs[0] += c4 * 19;
+        limbs[1] += c0;
+        limbs[2] += c1;
+        limbs[3] += c2;
+        limbs[4] += c3;
+        Fe(limbs)
+    }
+
+    fn add(&self, other: &Fe) -> Fe {
+        let mut limbs = self.0;
+        for (limb, o) in limbs.iter_mut().zip(other.0.iter()) {
+            *limb += o;
+        }
+        Fe::reduce(limbs)
+    }
+
+    /// `self + 16p - other`, limbs of both are below 2^52 after `reduce`.
+    fn sub(&self, other: &Fe) -> Fe {
+        Fe::reduce([
+            (self.0[0] + 36028797018963664) - other.0[0],
+            (self.0[1] + 36028797018963952) - other.0[1],
+            (self.0[2] + 36028797018963952) - other.0[2],
+            (self.0[3] + 36028797018963952) - other.0[3],
+            (self.0[4] + 36028797018963952) - other.0[4],
+        ])
+    }
+
+    fn mul(&self, other: &Fe) -> Fe {
+        #[inline(always)]
+        fn m(x: u64, y: u64) -> u128 {
+            (x as u128) * (y as u128)
+        }
+        let a = &self.0;
+        let b = &other.0;
+        let b1_19 = b[1] * 19;
+        let b2_19 = b[2] * 19;
+        let b3_19 = b[3] * 19;
+        let b4_19 = b[4] * 19;
+
+        let c0 = m(a[0], b[0]) + m(a[4], b1_19) + m(a[3], b2_19) + m(a[2], b3_19) + m(a[1], b4_19);
+        let mut c1 = m(a[1], b[0]) + m(a[0], b[1]) + m(a[4], b2_19) + m(a[3], b3_19) + m(a[2], b4_19);
+        let mut c2 = m(a[2], b[0]) + m(a[1], b[1]) + m(a[0], b[2]) + m(a[4], b3_19) + m(a[3], b4_19);
+        let mut c3 = m(a[3], b[0]) + m(a[2], b[1]) + m(a[1], b[2]) + m(a[0], b[3]) + m(a[4], b4_19);
+        let mut c4 = m(a[4], b[0]) + m(a[3], b[1]) + m(a[2], b[2]) + m(a[1], b[3]) + m(a[0], b[4]);
+
+        c1 += (c0 >> 51) as u64 as u128;
+        let mut out0 = (c0 as u64) & LOW_51_BITS;
+        c2 += (c1 >> 51) as u64 as u128;
+        let mut out1 = (c1 as u64) & LOW_51_BITS;
+        c3 += (c2 >> 51) as u64 as u128;
+        let out2 = (c2 as u64) & LOW_51_BITS;
+        c4 += (c3 >> 51) as u64 as u128;
+        let out3 = (c3 as u64) & LOW_51_BITS;
+        let carry = (c4 >> 51) as u64;
+        let out4 = (c4 as u64) & LOW_51_BITS;
+
+        out0 += carry * 19;
+        out1 += out0 >> 51;
+        out0 &= LOW_51_BITS;
+        Fe([out0, out1, out2, out3, out4])
+    }
+
+    fn square_times(&self, times: u32) -> Fe {
+        let mut result = *self;
+        for _ in 0..times {
+            result = result.mul(&result);
+        }
+        result
+    }
+
+    /// `self^(p - 2)` with the usual addition chain.
+    fn invert(&self) -> Fe {
+        let t0 = self.square_times(1);
+        let t1 = t0.square_times(2);
+        let t2 = self.mul(&t1);
+        let t3 = t0.mul(&t2);
+        let t4 = t3.square_times(1);
+        let t5 = t2.mul(&t4);
+        let t6 = t5.square_times(5);
+        let t7 = t6.mul(&t5);
+        let t8 = t7.square_times(10);
+        let t9 = t8.mul(&t7);
+        let t10 = t9.square_times(20);
+        let t11 = t10.mul(&t9);
+        let t12 = t11.square_times(10);
+        let t13 = t12.mul(&t7);
+        let t14 = t13.square_times(50);
+        let t15 = t14.mul(&t13);
+        let t16 = t15.square_times(100);
+        let t17 = t16.mul(&t15);
+        let t18 = t17.square_times(50);
+        let t19 = t18.mul(&t13);
+        let t20 = t19.square_times(5);
+        t20.mul(&t3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+    use curve25519_dalek::scalar::Scalar;
+    use ed25519_dalek::{ExpandedSecretKey, PublicKey};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn reference(base: &[u8; 32], index: u64) -> [u8; 32] {
+        (&Scalar::from_bits(add_multiple_of_8(base, index)) * &ED25519_BASEPOINT_TABLE).compress().to_bytes()
+    }
+
+    fn base() -> [u8; 32] {
+        IncrementalKeys::random_base(&mut StdRng::seed_from_u64(1))
+    }
+
+    #[test]
+    fn first_key_matches_dalek() {
+        let base = base();
+        let (index, public) = IncrementalKeys::from_base(base, 0).next();
+        assert_eq!(index, 0);
+        assert_eq!(public, reference(&base, 0));
+    }
+
+    #[test]
+    fn keys_match_dalek_across_batches() {
+        let base = base();
+        let mut keys = IncrementalKeys::from_base(base, 0);
+        for expected in 0..(IncrementalKeys::BATCH as u64 * 2 + 3) {
+            let (index, public) = keys.next();
+            assert_eq!(index, expected);
+            assert_eq!(public, reference(&base, index), "key {}", index);
+        }
+    }
+
+    #[test]
+    fn stream_from_non_zero_index() {
+        let base = base();
+        let start = 1_000_000_007;
+        let mut keys = IncrementalKeys::from_base(base, start);
+        for expected in start..start + IncrementalKeys::BATCH as u64 + 1 {
+            let (index, public) = keys.next();
+            assert_eq!(index, expected);
+            assert_eq!(public, reference(&base, index), "key {}", index);
+        }
+    }
+
+    #[test]
+    fn expanded_secret_rebuilds_public_key() {
+        let base = base();
+        let mut keys = IncrementalKeys::from_base(base, 250);
+        for _ in 0..10 {
+            let (index, public) = keys.next();
+            let secret = ExpandedSecretKey::from_bytes(&IncrementalKeys::expanded_secret(&base, index)).unwrap();
+            assert_eq!(PublicKey::from(&secret).to_bytes(), public);
+        }
+    }
+
+    #[test]
+    fn random_base_is_clamped() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..100 {
+            let base = IncrementalKeys::random_base(&mut rng);
+            assert_eq!(base[0] & 7, 0);
+            assert_eq!(base[31] & 0xe0, 0x40);
+        }
+    }
+}
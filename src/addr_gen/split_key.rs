@@ -0,0 +1,135 @@
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha512};
+
+/// Split-key search: workers only know the customer public point `A` and look for a tweak `t`
+/// such that `A + t*G` gives a matching address. The customer key `a + t` is combined offline.
+#[derive(Clone, Copy)]
+pub struct SplitKey {
+    base: EdwardsPoint,
+}
+
+impl SplitKey {
+    pub fn from_public(hex_key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_key.trim())
+            .map_err(|e| format!("unable to decode split key public as hex: {}", e))?;
+        if bytes.len() != 32 {
+            return Err(format!("split key public has {} bytes, 32 expected", bytes.len()));
+        }
+        let base = CompressedEdwardsY::from_slice(&bytes).decompress()
+            .ok_or_else(|| "split key public is not a valid ed25519 public key".to_string())?;
+        Ok(Self { base })
+    }
+
+    /// Random tweak and the compressed public key `A + t*G`.
+    pub fn random_tweak<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (Scalar, [u8; 32]) {
+        let tweak = Scalar::random(rng);
+        (tweak, (self.base + &tweak * &ED25519_BASEPOINT_TABLE).compress().to_bytes())
+    }
+}
+
+/// Customer secret scalar and nonce prefix from a 32 bytes ed25519 secret or a 64 bytes expanded one.
+fn customer_secret(hex_key: &str) -> Result<(Scalar, [u8; 32]), String> {
+    let bytes = hex::decode(hex_key.trim())
+        .map_err(|e| format!("unable to decode customer secret as hex: {}", e))?;
+    let expanded = match bytes.len() {
+        32 => {
+            let secret = SecretKey::from_bytes(&bytes)
+                .map_err(|e| format!("invalid customer secret: {}", e))?;
+            ExpandedSecretKey::from(&secret).to_bytes()
+        }
+        64 => {
+            let mut expanded = [0u8; 64];
+            expanded.copy_from_slice(&bytes);
+            expanded
+        }
+        len => return Err(format!("customer secret has {} bytes, 32 or 64 expected", len)),
+    };
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&expanded[..32]);
+    let mut nonce = [0u8; 32];
+    nonce.copy_from_slice(&expanded[32..]);
+    Ok((Scalar::from_bytes_mod_order(scalar), nonce))
+}
+
+pub fn parse_tweak(hex_tweak: &str) -> Result<Scalar, String> {
+    let hex_tweak = hex_tweak.trim();
+    let bytes = hex::decode(hex_tweak.strip_prefix("tweak:").unwrap_or(hex_tweak))
+        .map_err(|e| format!("unable to decode tweak as hex: {}", e))?;
+    if bytes.len() != 32 {
+        return Err(format!("tweak has {} bytes, 32 expected", bytes.len()));
+    }
+    let mut tweak = [0u8; 32];
+    tweak.copy_from_slice(&bytes);
+    Scalar::from_canonical_bytes(tweak).ok_or_else(|| "tweak is not a canonical scalar".to_string())
+}
+
+/// Expanded secret key `(a + t) || nonce` and its public key, checked by signing a test message.
+/// The result is a 64 bytes expanded key, there is no 32 bytes seed for it.
+pub fn combine(customer_secret_hex: &str, tweak_hex: &str) -> Result<([u8; 64], PublicKey), String> {
+    let (scalar, nonce) = customer_secret(customer_secret_hex)?;
+    let combined = scalar + parse_tweak(tweak_hex)?;
+
+    let mut nonce_hash = Sha512::new();
+    nonce_hash.input(b"address_gen split key nonce");
+    nonce_hash.input(nonce);
+    nonce_hash.input(combined.as_bytes());
+    let mut expanded = [0u8; 64];
+    expanded[..32].copy_from_slice(combined.as_bytes());
+    expanded[32..].copy_from_slice(&nonce_hash.result()[..32]);
+
+    let public = PublicKey::from_bytes((&combined * &ED25519_BASEPOINT_TABLE).compress().as_bytes())
+        .map_err(|e| format!("unable to build combined public key: {}", e))?;
+    let key = ExpandedSecretKey::from_bytes(&expanded)
+        .map_err(|e| format!("unable to build combined secret key: {}", e))?;
+    let message = b"address_gen split key check";
+    public.verify_strict(message, &key.sign(message, &public))
+        .map_err(|e| format!("combined key doesn't sign for its public key: {}", e))?;
+    Ok((expanded, public))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const CUSTOMER_SEED: [u8; 32] = [9; 32];
+
+    fn customer_public() -> String {
+        hex::encode(PublicKey::from(&SecretKey::from_bytes(&CUSTOMER_SEED).unwrap()).as_bytes())
+    }
+
+    #[test]
+    fn combined_key_matches_tweaked_public() {
+        let split_key = SplitKey::from_public(&customer_public()).unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..5 {
+            let (tweak, public) = split_key.random_tweak(&mut rng);
+            let tweak_hex = format!("tweak:{}", hex::encode(tweak.as_bytes()));
+            for secret in &[hex::encode(CUSTOMER_SEED),
+                            hex::encode(&ExpandedSecretKey::from(&SecretKey::from_bytes(&CUSTOMER_SEED).unwrap()).to_bytes()[..])] {
+                let (expanded, combined) = combine(secret, &tweak_hex).unwrap();
+                assert_eq!(combined.to_bytes(), public);
+                // `a + t` is not clamped, the public key is its plain multiple of the base point
+                let mut scalar = [0u8; 32];
+                scalar.copy_from_slice(&expanded[..32]);
+                assert_eq!((&Scalar::from_bits(scalar) * &ED25519_BASEPOINT_TABLE).compress().to_bytes(), public);
+                let key = ExpandedSecretKey::from_bytes(&expanded).unwrap();
+                assert!(combined.verify_strict(b"message", &key.sign(b"message", &combined)).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_tweaks_are_rejected() {
+        let secret = hex::encode(CUSTOMER_SEED);
+        for tweak in &["tweak:zz", "tweak:0102", &format!("tweak:{}", "ff".repeat(32)), &format!("key:{}", "01".repeat(32))] {
+            assert!(combine(&secret, tweak).is_err(), "{}", tweak);
+        }
+        assert!(parse_tweak(&format!("tweak:{}", "01".repeat(32))).is_ok());
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::Cursor;
+use sha2::{Digest, Sha256};
+use ton_sdk::ContractImage;
+use ton_types::{Cell, CellType};
+use ton_types::cells_serialization::deserialize_tree_of_cells;
+use crate::addr_checker::nibbles::AddressHash;
+
+const KEY_BITS: usize = 256;
+
+/// Ordinary cell on the way from the StateInit root to the cell holding the public key, with
+/// everything but the changing data or child hash precomputed.
+struct PathCell {
+    descriptor: [u8; 2],
+    data: Vec<u8>,
+    depths: Vec<[u8; 2]>,
+    hashes: Vec<[u8; 32]>,
+    child: usize,
+}
+
+impl PathCell {
+    fn from_cell(cell: &Cell, child: usize) -> Result<Self, String> {
+        if cell.cell_type() != CellType::Ordinary || cell.level() != 0 {
+            return Err("contract has exotic cells on the path to the public key".to_string());
+        }
+        let bits = cell.bit_length();
+        let refs = cell.references_count();
+        let mut data = cell.data()[..(bits + 7) / 8].to_vec();
+        if bits % 8 != 0 {
+            // completion tag: one bit set right after the data, the rest cleared
+            let last = data.len() - 1;
+            data[last] &= 0xff << (8 - bits % 8);
+            data[last] |= 0x80 >> (bits % 8);
+        }
+        let mut depths = vec![];
+        let mut hashes = vec![];
+        for i in 0..refs {
+            let reference = cell.reference(i)
+                .map_err(|e| format!("unable to read cell reference: {}", e))?;
+            depths.push(reference.repr_depth().to_be_bytes());
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(&reference.repr_hash().as_slice()[..]);
+            hashes.push(hash);
+        }
+        Ok(Self {
+            descriptor: [refs as u8, ((bits / 8) + (bits + 7) / 8) as u8],
+            data,
+            depths,
+            hashes,
+            child,
+        })
+    }
+
+    /// Representation hash with `child_hash` in place of the reference on the path.
+    fn hash(&self, data: &[u8], child_hash: Option<&[u8; 32]>) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.input(&self.descriptor);
+        hasher.input(data);
+        for depth in &self.depths {
+            hasher.input(depth);
+        }
+        for (i, hash) in self.hashes.iter().enumerate() {
+            match child_hash {
+                Some(child_hash) if i == self.child => hasher.input(child_hash),
+                _ => hasher.input(hash),
+            }
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.result());
+        hash
+    }
+}
+
+/// Account id of the contract for a public key without rebuilding the `ContractImage`: only the
+/// cell holding the key and the cells above it are rehashed, the code cell and the rest of the
+/// data are hashed once. Depths don't change since the key has a fixed size.
+pub struct StateInitHasher {
+    path: Vec<PathCell>,
+    key_offset: usize,
+}
+
+impl StateInitHasher {
+    /// Finds the key cell by setting a marker public key on the image.
+    #[allow(unused_must_use)]
+    pub fn new(contract_image: &mut ContractImage, marker: &ed25519_dalek::PublicKey) -> Result<Self, String> {
+        contract_image.set_public_key(marker);
+        let boc = contract_image.serialize()
+            .map_err(|e| format!("unable to serialize contract image: {}", e))?;
+        let root = deserialize_tree_of_cells(&mut Cursor::new(boc))
+            .map_err(|e| format!("unable to deserialize contract image: {}", e))?;
+
+        let mut found = vec![];
+        Self::find_key(&root, marker.as_bytes(), &mut vec![], &mut found)?;
+        let (indexes, key_offset) = match found.len() {
+            1 => found.remove(0),
+            0 => return Err("public key is not found in contract data".to_string()),
+            _ => return Err("public key is found more than once in contract data".to_string()),
+        };
+
+        let mut path = vec![];
+        let mut cell = root;
+        for index in &indexes {
+            path.push(PathCell::from_cell(&cell, *index)?);
+            cell = cell.reference(*index)
+                .map_err(|e| format!("unable to read cell reference: {}", e))?;
+        }
+        path.push(PathCell::from_cell(&cell, 0)?);
+        Ok(Self { path, key_offset })
+    }
+
+    /// Paths (reference indexes from the root) and bit offsets of every copy of the key.
+    fn find_key(cell: &Cell, key: &[u8; 32], indexes: &mut Vec<usize>,
+                found: &mut Vec<(Vec<usize>, usize)>) -> Result<(), String> {
+        let bits = cell.bit_length();
+        let data = cell.data();
+        if bits >= KEY_BITS {
+            for offset in 0..=bits - KEY_BITS {
+                if (0..KEY_BITS).all(|i| get_bit(data, offset + i) == get_bit(key, i)) {
+                    found.push((indexes.clone(), offset));
+                }
+            }
+        }
+        for i in 0..cell.references_count() {
+            let reference = cell.reference(i)
+                .map_err(|e| format!("unable to read cell reference: {}", e))?;
+            indexes.push(i);
+            Self::find_key(&reference, key, indexes, found)?;
+            indexes.pop();
+        }
+        Ok(())
+    }
+
+    pub fn hash(&self, public: &[u8; 32]) -> AddressHash {
+        let key_cell = &self.path[self.path.len() - 1];
+        let mut data = [0u8; 128];
+        let data = &mut data[..key_cell.data.len()];
+        data.copy_from_slice(&key_cell.data);
+        for i in 0..KEY_BITS {
+            set_bit(data, self.key_offset + i, get_bit(public, i));
+        }
+        let mut hash = key_cell.hash(data, None);
+        for cell in self.path.iter().rev().skip(1) {
+            hash = cell.hash(&cell.data, Some(&hash));
+        }
+        hash
+    }
+}
+
+#[inline]
+fn get_bit(data: &[u8], index: usize) -> bool {
+    data[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+#[inline]
+fn set_bit(data: &mut [u8], index: usize, value: bool) {
+    if value {
+        data[index / 8] |= 0x80 >> (index % 8);
+    } else {
+        data[index / 8] &= !(0x80 >> (index % 8));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{PublicKey, SecretKey};
+    use ton_types::BuilderData;
+    use crate::addr_gen::AccountGenerator;
+
+    fn key(seed: u8) -> PublicKey {
+        PublicKey::from(&SecretKey::from_bytes(&[seed; 32]).unwrap())
+    }
+
+    fn hash_of(cell: &Cell) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&cell.repr_hash().as_slice()[..]);
+        hash
+    }
+
+    fn check_contract(file: &str) {
+        let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file);
+        let mut generator = AccountGenerator::from_tvc_file(&path, 0).unwrap();
+        let hasher = StateInitHasher::new(&mut generator.contract_image, &key(1)).unwrap();
+        for seed in 2..18 {
+            let public = key(seed);
+            assert_eq!(hasher.hash(public.as_bytes()), generator.image_address_hash(&public), "key {}", seed);
+        }
+    }
+
+    #[test]
+    fn setcode_multisig_matches_contract_image() {
+        check_contract("SetcodeMultisigWallet.tvc");
+    }
+
+    #[test]
+    fn safe_multisig_matches_contract_image() {
+        check_contract("SafeMultisigWallet.tvc");
+    }
+
+    #[test]
+    fn path_cell_hashes_unaligned_data() {
+        let child = BuilderData::with_raw(vec![0xab], 8).unwrap().into_cell().unwrap();
+        for bits in &[1, 7, 13, 255, 257] {
+            let mut builder = BuilderData::with_raw(vec![0x5a; (bits + 7) / 8], *bits).unwrap();
+            builder.checked_append_reference(child.clone()).unwrap();
+            let cell = builder.into_cell().unwrap();
+            let path_cell = PathCell::from_cell(&cell, 0).unwrap();
+            assert_eq!(path_cell.hash(&path_cell.data, None), hash_of(&cell), "{} bits", bits);
+            assert_eq!(path_cell.hash(&path_cell.data, Some(&hash_of(&child))), hash_of(&cell), "{} bits", bits);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::addr_checker::nibbles::AddressHash;
+
+pub const USER_FRIENDLY_LEN: usize = 48;
+
+const BOUNCEABLE_TAG: u8 = 0x11;
+const NON_BOUNCEABLE_TAG: u8 = 0x51;
+const TESTNET_FLAG: u8 = 0x80;
+
+/// Flags of the 48-char user-friendly address: base64 alphabet, bounceable and testnet bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FriendlyFormat {
+    pub url_safe: bool,
+    pub bounceable: bool,
+    pub testnet: bool,
+}
+
+impl Default for FriendlyFormat {
+    fn default() -> Self {
+        Self { url_safe: true, bounceable: true, testnet: false }
+    }
+}
+
+impl FriendlyFormat {
+    pub fn with_url_safe(self, url_safe: bool) -> Self {
+        Self { url_safe, ..self }
+    }
+
+    fn tag(&self) -> u8 {
+        let tag = if self.bounceable { BOUNCEABLE_TAG } else { NON_BOUNCEABLE_TAG };
+        if self.testnet { tag | TESTNET_FLAG } else { tag }
+    }
+}
+
+/// `tag | workchain | account hash | crc16` encoded as base64 into a stack buffer.
+pub fn encode_user_friendly<'a>(workchain: i8, hash: &AddressHash, format: FriendlyFormat,
+                                buffer: &'a mut [u8; USER_FRIENDLY_LEN]) -> &'a str {
+    let mut raw = [0u8; 36];
+    raw[0] = format.tag();
+    raw[1] = workchain as u8;
+    raw[2..34].copy_from_slice(hash);
+    let crc = crc16(&raw[..34]);
+    raw[34] = (crc >> 8) as u8;
+    raw[35] = (crc & 0xff) as u8;
+    let config = if format.url_safe { base64::URL_SAFE } else { base64::STANDARD };
+    base64::encode_config_slice(raw, config, buffer);
+    std::str::from_utf8(buffer).unwrap()
+}
+
+pub fn user_friendly(workchain: i8, hash: &AddressHash, format: FriendlyFormat) -> String {
+    let mut buffer = [0u8; USER_FRIENDLY_LEN];
+    encode_user_friendly(workchain, hash, format, &mut buffer).to_string()
+}
+
+/// CRC16-XMODEM as used in TON user-friendly addresses.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8";
+
+    fn account() -> AddressHash {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hex::decode(ACCOUNT).unwrap());
+        hash
+    }
+
+    fn format(bounceable: bool, testnet: bool) -> FriendlyFormat {
+        FriendlyFormat { url_safe: true, bounceable, testnet }
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn known_addresses() {
+        let hash = account();
+        assert_eq!(user_friendly(0, &hash, format(true, false)), "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N");
+        assert_eq!(user_friendly(0, &hash, format(false, false)), "UQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqEBI");
+        assert_eq!(user_friendly(0, &hash, format(true, true)), "kQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqKYH");
+        assert_eq!(user_friendly(0, &hash, format(false, true)), "0QCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqPvC");
+    }
+
+    #[test]
+    fn base64_and_base64url() {
+        let hash = [0xfb; 32];
+        let url_safe = FriendlyFormat::default();
+        assert_eq!(user_friendly(-1, &hash, url_safe), "Ef_7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-_v7-75c");
+        assert_eq!(user_friendly(-1, &hash, url_safe.with_url_safe(false)),
+                   "Ef/7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+75c");
+    }
+}
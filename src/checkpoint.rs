@@ -0,0 +1,268 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Deserialize, Serialize};
+
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Saved position of one worker: every key below `next_index` is checked, and hits up to
+/// `last_saved` (which may be past `next_index`) are already in the results.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ThreadCheckpoint {
+    pub base: String,
+    pub next_index: u64,
+    pub last_saved: Option<u64>,
+}
+
+/// State file of an incremental search. It holds the base secret scalars, so it is written with
+/// 0600 permissions.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub contract_hash: String,
+    pub workchain: i8,
+    pub threads: Vec<ThreadCheckpoint>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read checkpoint: {}", e))?;
+        let checkpoint: Self = serde_json::from_str(&data)
+            .map_err(|e| format!("unable to parse checkpoint: {}", e))?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(format!("unsupported checkpoint version {}", checkpoint.version));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Loads a checkpoint to continue, it has to be made for the same contract, workchain and
+    /// amount of threads.
+    pub fn resume(path: &str, contract_hash: &str, workchain: i8, threads: usize) -> Result<Self, String> {
+        let checkpoint = Self::load(path)?;
+        if checkpoint.contract_hash != contract_hash || checkpoint.workchain != workchain {
+            return Err("checkpoint was made for another contract or workchain".to_string());
+        }
+        if checkpoint.threads.len() != threads {
+            return Err(format!("checkpoint was made with {} threads, run with the same amount",
+                               checkpoint.threads.len()));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Checkpoint of a new search with one stream per base, an existing file is never overwritten.
+    pub fn create(path: &str, contract_hash: String, workchain: i8, bases: &[[u8; 32]]) -> Result<Self, String> {
+        if Path::new(path).exists() {
+            return Err(format!("checkpoint {} exists, continue it with --resume or remove it", path));
+        }
+        Ok(Self {
+            version: CHECKPOINT_VERSION,
+            contract_hash,
+            workchain,
+            threads: bases.iter()
+                .map(|base| ThreadCheckpoint { base: hex::encode(base), next_index: 0, last_saved: None })
+                .collect(),
+        })
+    }
+
+    /// Writes to `<path>.tmp` and renames it over `path`, so a crash never leaves a torn file.
+    /// A stale tmp file is removed first, so the new one always gets 0600 permissions.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let tmp = format!("{}.tmp", path);
+        if let Err(e) = fs::remove_file(&tmp) {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(format!("unable to remove stale checkpoint: {}", e));
+            }
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp)
+            .map_err(|e| format!("unable to create checkpoint: {}", e))?;
+        file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("unable to write checkpoint: {}", e))?;
+        fs::rename(&tmp, path)
+            .map_err(|e| format!("unable to replace checkpoint: {}", e))
+    }
+}
+
+/// Positions of the workers, advanced by workers after every chunk and saved by the writer thread
+/// after every successful save of hits.
+pub struct Progress {
+    path: String,
+    contract_hash: String,
+    workchain: i8,
+    bases: Vec<[u8; 32]>,
+    next: Vec<AtomicU64>,
+    /// `index + 1` of the last saved hit, 0 if none.
+    saved: Vec<AtomicU64>,
+    /// Hits up to these indexes are saved by the run the checkpoint was loaded from.
+    resumed: Vec<Option<u64>>,
+}
+
+impl Progress {
+    pub fn new(path: &str, checkpoint: &Checkpoint) -> Result<Self, String> {
+        let mut bases = vec![];
+        for thread in &checkpoint.threads {
+            let bytes = hex::decode(&thread.base)
+                .map_err(|e| format!("invalid base in checkpoint: {}", e))?;
+            if bytes.len() != 32 {
+                return Err(format!("invalid base in checkpoint: {} bytes, 32 expected", bytes.len()));
+            }
+            let mut base = [0u8; 32];
+            base.copy_from_slice(&bytes);
+            bases.push(base);
+        }
+        let resumed: Vec<Option<u64>> = checkpoint.threads.iter().map(|t| t.last_saved).collect();
+        Ok(Self {
+            path: path.to_string(),
+            contract_hash: checkpoint.contract_hash.clone(),
+            workchain: checkpoint.workchain,
+            bases,
+            next: checkpoint.threads.iter().map(|t| AtomicU64::new(t.next_index)).collect(),
+            saved: resumed.iter().map(|s| AtomicU64::new(s.map_or(0, |s| s + 1))).collect(),
+            resumed,
+        })
+    }
+
+    /// Base and first index of the stream of `thread`.
+    pub fn start(&self, thread: usize) -> ([u8; 32], u64) {
+        (self.bases[thread], self.next[thread].load(Ordering::Relaxed))
+    }
+
+    /// Release pairs with `positions`: hits sent before advancing are visible to the writer.
+    pub fn advance(&self, thread: usize, attempts: u64) {
+        self.next[thread].fetch_add(attempts, Ordering::Release);
+    }
+
+    /// Hit saved before the resume, found again since the checkpoint lags behind saves.
+    pub fn is_saved(&self, thread: usize, index: u64) -> bool {
+        self.resumed[thread].map_or(false, |saved| index <= saved)
+    }
+
+    pub fn mark_saved(&self, thread: usize, index: u64) {
+        self.saved[thread].fetch_max(index + 1, Ordering::Relaxed);
+    }
+
+    pub fn positions(&self) -> Vec<u64> {
+        self.next.iter().map(|n| n.load(Ordering::Acquire)).collect()
+    }
+
+    /// Saves `positions` taken before the hits below them were saved.
+    pub fn save(&self, positions: &[u64]) -> Result<(), String> {
+        let threads = self.bases.iter().zip(positions).zip(&self.saved)
+            .map(|((base, next), saved)| ThreadCheckpoint {
+                base: hex::encode(base),
+                next_index: *next,
+                last_saved: saved.load(Ordering::Relaxed).checked_sub(1),
+            })
+            .collect();
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            contract_hash: self.contract_hash.clone(),
+            workchain: self.workchain,
+            threads,
+        }.save(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTRACT: &str = "e2b60b6b";
+
+    /// Fresh path in the temp dir, removed with its tmp file.
+    fn path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("address_gen_{}_{}.json", std::process::id(), name));
+        let path = path.to_str().unwrap().to_string();
+        fs::remove_file(&path).ok();
+        fs::remove_file(format!("{}.tmp", path)).ok();
+        path
+    }
+
+    fn new_checkpoint(path: &str, threads: usize) -> Checkpoint {
+        let bases: Vec<[u8; 32]> = (0..threads).map(|thread| [thread as u8 + 1; 32]).collect();
+        Checkpoint::create(path, CONTRACT.to_string(), 0, &bases).unwrap()
+    }
+
+    #[test]
+    fn save_and_resume() {
+        let path = path("resume");
+        let progress = Progress::new(&path, &new_checkpoint(&path, 2)).unwrap();
+        progress.advance(0, 1000);
+        progress.advance(1, 700);
+        progress.mark_saved(1, 650);
+        progress.save(&progress.positions()).unwrap();
+
+        let checkpoint = Checkpoint::resume(&path, CONTRACT, 0, 2).unwrap();
+        assert_eq!(checkpoint.threads[1].base, hex::encode([2u8; 32]));
+        assert_eq!((checkpoint.threads[0].next_index, checkpoint.threads[0].last_saved), (1000, None));
+        assert_eq!((checkpoint.threads[1].next_index, checkpoint.threads[1].last_saved), (700, Some(650)));
+        let resumed = Progress::new(&path, &checkpoint).unwrap();
+        assert_eq!(resumed.start(1), ([2u8; 32], 700));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn resume_mismatches() {
+        let path = path("mismatch");
+        new_checkpoint(&path, 2).save(&path).unwrap();
+        assert!(Checkpoint::resume(&path, CONTRACT, 0, 2).is_ok());
+        assert!(Checkpoint::resume(&path, "80d6c47c", 0, 2).is_err());
+        assert!(Checkpoint::resume(&path, CONTRACT, -1, 2).is_err());
+        assert!(Checkpoint::resume(&path, CONTRACT, 0, 3).is_err());
+        assert!(Checkpoint::resume(&format!("{}.missing", path), CONTRACT, 0, 2).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let path = path("version");
+        let mut checkpoint = new_checkpoint(&path, 1);
+        checkpoint.version = CHECKPOINT_VERSION + 1;
+        checkpoint.save(&path).unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn existing_checkpoint_is_not_overwritten() {
+        let path = path("existing");
+        new_checkpoint(&path, 1).save(&path).unwrap();
+        assert!(Checkpoint::create(&path, CONTRACT.to_string(), 0, &[[1; 32]]).is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn saved_hits_at_the_boundary() {
+        let path = path("saved");
+        let mut checkpoint = new_checkpoint(&path, 2);
+        checkpoint.threads[0].last_saved = Some(40);
+        let progress = Progress::new(&path, &checkpoint).unwrap();
+        assert!(progress.is_saved(0, 0));
+        assert!(progress.is_saved(0, 40));
+        assert!(!progress.is_saved(0, 41));
+        assert!(!progress.is_saved(1, 0));
+        // hits saved by this run are not skipped, only the ones from before the resume
+        progress.mark_saved(1, 10);
+        assert!(!progress.is_saved(1, 10));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_tmp_file_gets_private_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = path("permissions");
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, "stale").unwrap();
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o644)).unwrap();
+        new_checkpoint(&path, 1).save(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(Checkpoint::load(&path).is_ok());
+        fs::remove_file(&path).ok();
+    }
+}
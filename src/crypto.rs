@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::aead::generic_array::GenericArray;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use hkdf::Hkdf;
+use hmac::Hmac;
+use lazy_static::lazy_static;
+use pbkdf2::pbkdf2;
+use rand::RngCore;
+use regex::Regex;
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Environment variable with the passphrase, never taken from args to keep it out of `ps`.
+pub const PASSPHRASE_ENV: &str = "VANITY_PASSPHRASE";
+pub const SEALED_PREFIX: &str = "enc:v1:";
+const PBKDF2_ROUNDS: usize = 100_000;
+const HKDF_INFO: &[u8] = b"address_gen enc:v1 x25519";
+
+lazy_static! {
+    static ref SEALED_VALUE: Regex = Regex::new(r"enc:v1:[a-z0-9]+(:[A-Za-z0-9_-]+)+").unwrap();
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn from_b64(value: &str) -> Result<Vec<u8>, String> {
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| format!("unable to decode sealed value: {}", e))
+}
+
+fn to_array<T: AsMut<[u8]> + Default>(bytes: &[u8]) -> Result<T, String> {
+    let mut array = T::default();
+    if array.as_mut().len() != bytes.len() {
+        return Err(format!("sealed value part has {} bytes, {} expected", bytes.len(), array.as_mut().len()));
+    }
+    array.as_mut().copy_from_slice(bytes);
+    Ok(array)
+}
+
+pub fn parse_key(hex_key: &str) -> Result<[u8; 32], String> {
+    let bytes = hex::decode(hex_key.trim())
+        .map_err(|e| format!("unable to decode key as hex: {}", e))?;
+    to_array(&bytes).map_err(|_| format!("key has {} bytes, 32 expected", bytes.len()))
+}
+
+/// Recipient public key: `x25519:<hex>`, `ed25519:<hex>` (converted to its X25519 form) or bare X25519 hex.
+pub fn parse_recipient(key: &str) -> Result<PublicKey, String> {
+    let key = key.trim();
+    if let Some(hex_key) = key.strip_prefix("ed25519:") {
+        let point = CompressedEdwardsY(parse_key(hex_key)?).decompress()
+            .ok_or_else(|| "ed25519 public key is not a valid curve point".to_string())?;
+        return Ok(PublicKey::from(point.to_montgomery().to_bytes()));
+    }
+    Ok(PublicKey::from(parse_key(key.strip_prefix("x25519:").unwrap_or(key))?))
+}
+
+/// Private key matching `parse_recipient`, for ed25519 it is the secret seed.
+pub fn parse_identity(key: &str) -> Result<StaticSecret, String> {
+    let key = key.trim();
+    if let Some(hex_key) = key.strip_prefix("ed25519:") {
+        // ed25519 secret scalar is the lower half of sha512(seed), StaticSecret clamps it the same way
+        let hash = Sha512::digest(&parse_key(hex_key)?);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&hash[..32]);
+        return Ok(StaticSecret::from(scalar));
+    }
+    Ok(StaticSecret::from(parse_key(key.strip_prefix("x25519:").unwrap_or(key))?))
+}
+
+/// New X25519 identity, returns `(private, public)` in `x25519:<hex>` form.
+pub fn generate_identity() -> (String, String) {
+    let secret = StaticSecret::new(rand::thread_rng());
+    let public = PublicKey::from(&secret);
+    (format!("x25519:{}", hex::encode(secret.to_bytes())), format!("x25519:{}", hex::encode(public.as_bytes())))
+}
+
+/// Sealed values found in a line, e.g. a whole csv row or json line of a record.
+pub fn find_sealed(line: &str) -> Vec<(usize, usize)> {
+    SEALED_VALUE.find_iter(line).map(|m| (m.start(), m.end())).collect()
+}
+
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Every sealed value has its own ephemeral key, so the nonce may be fixed.
+fn recipient_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt[..]), shared)
+        .expand(HKDF_INFO, &mut key)
+        .expect("32 bytes is a valid hkdf output length");
+    key
+}
+
+fn encrypt(key: &[u8; 32], nonce: &[u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(GenericArray::from_slice(key))
+        .encrypt(GenericArray::from_slice(nonce), plaintext)
+        .expect("chacha20poly1305 encryption of a short value never fails")
+}
+
+fn decrypt(key: &[u8; 32], nonce: &[u8; 12], ciphertext: &[u8]) -> Result<String, String> {
+    let plaintext = ChaCha20Poly1305::new(GenericArray::from_slice(key))
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|_| "unable to decrypt value: wrong key or corrupted data".to_string())?;
+    String::from_utf8(plaintext).map_err(|e| format!("decrypted value is not utf8: {}", e))
+}
+
+/// Seals secret fields of saved records with ChaCha20-Poly1305:
+/// `enc:v1:pass:<salt>:<nonce>:<ciphertext>` with a PBKDF2 key derived once per run, or
+/// `enc:v1:x25519:<ephemeral public>:<ciphertext>` for a recipient key, which workers can't open.
+pub enum Sealer {
+    Passphrase { salt: [u8; 16], key: [u8; 32] },
+    Recipient { public: PublicKey },
+}
+
+impl Sealer {
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Sealer::Passphrase { salt, key: passphrase_key(passphrase, &salt) }
+    }
+
+    pub fn from_recipient(key: &str) -> Result<Self, String> {
+        Ok(Sealer::Recipient { public: parse_recipient(key)? })
+    }
+
+    /// Empty values (e.g. no mnemonic) stay empty.
+    pub fn seal(&self, plaintext: &str) -> String {
+        if plaintext.is_empty() {
+            return String::new();
+        }
+        match self {
+            Sealer::Passphrase { salt, key } => {
+                let mut nonce = [0u8; 12];
+                rand::thread_rng().fill_bytes(&mut nonce);
+                let ciphertext = encrypt(key, &nonce, plaintext.as_bytes());
+                format!("{}pass:{}:{}:{}", SEALED_PREFIX, b64(salt), b64(&nonce), b64(&ciphertext))
+            }
+            Sealer::Recipient { public } => {
+                let ephemeral = EphemeralSecret::new(rand::thread_rng());
+                let ephemeral_public = PublicKey::from(&ephemeral);
+                let shared = ephemeral.diffie_hellman(public);
+                let key = recipient_key(shared.as_bytes(), &ephemeral_public, public);
+                let ciphertext = encrypt(&key, &[0u8; 12], plaintext.as_bytes());
+                format!("{}x25519:{}:{}", SEALED_PREFIX, b64(ephemeral_public.as_bytes()), b64(&ciphertext))
+            }
+        }
+    }
+}
+
+/// Opens values sealed by `Sealer`, the passphrase key is derived once per salt.
+pub enum Opener {
+    Passphrase { passphrase: String, keys: HashMap<Vec<u8>, [u8; 32]> },
+    Identity { secret: StaticSecret },
+}
+
+impl Opener {
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Opener::Passphrase { passphrase: passphrase.to_string(), keys: HashMap::new() }
+    }
+
+    pub fn from_identity(key: &str) -> Result<Self, String> {
+        Ok(Opener::Identity { secret: parse_identity(key)? })
+    }
+
+    pub fn open(&mut self, value: &str) -> Result<String, String> {
+        let body = value.strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| format!("value doesn't start with \"{}\"", SEALED_PREFIX))?;
+        let parts: Vec<&str> = body.split(':').collect();
+        match (self, parts.as_slice()) {
+            (Opener::Passphrase { passphrase, keys }, ["pass", salt, nonce, ciphertext]) => {
+                let salt = from_b64(salt)?;
+                let key = *keys.entry(salt.clone()).or_insert_with(|| passphrase_key(passphrase, &salt));
+                decrypt(&key, &to_array(&from_b64(nonce)?)?, &from_b64(ciphertext)?)
+            }
+            (Opener::Identity { secret }, ["x25519", ephemeral, ciphertext]) => {
+                let ephemeral = PublicKey::from(to_array::<[u8; 32]>(&from_b64(ephemeral)?)?);
+                let shared = secret.diffie_hellman(&ephemeral);
+                let key = recipient_key(shared.as_bytes(), &ephemeral, &PublicKey::from(&*secret));
+                decrypt(&key, &[0u8; 12], &from_b64(ciphertext)?)
+            }
+            (Opener::Passphrase { .. }, ["x25519", ..]) => Err("value is sealed to a recipient key, use --identity".to_string()),
+            (Opener::Identity { .. }, ["pass", ..]) => Err("value is sealed with a passphrase, drop --identity".to_string()),
+            _ => Err(format!("unknown sealed value format: {}", value)),
+        }
+    }
+
+    /// Replaces every sealed value in a line.
+    pub fn open_all(&mut self, line: &str) -> Result<String, String> {
+        let mut result = String::new();
+        let mut last = 0;
+        for (start, end) in find_sealed(line) {
+            result.push_str(&line[last..start]);
+            result.push_str(&self.open(&line[start..end])?);
+            last = end;
+        }
+        result.push_str(&line[last..]);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "4f1c2d3e";
+
+    #[test]
+    fn passphrase_round_trip() {
+        let sealed = Sealer::from_passphrase("correct horse").seal(SECRET);
+        assert!(sealed.starts_with("enc:v1:pass:"), "{}", sealed);
+        assert!(!sealed.contains(SECRET));
+        assert_eq!(Opener::from_passphrase("correct horse").open(&sealed).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn recipient_round_trip() {
+        let (private, public) = generate_identity();
+        let sealed = Sealer::from_recipient(&public).unwrap().seal(SECRET);
+        assert!(sealed.starts_with("enc:v1:x25519:"), "{}", sealed);
+        assert_eq!(Opener::from_identity(&private).unwrap().open(&sealed).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn ed25519_recipient_opens_with_seed() {
+        let seed = [7u8; 32];
+        let public = ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::from_bytes(&seed).unwrap());
+        let sealed = Sealer::from_recipient(&format!("ed25519:{}", hex::encode(public.as_bytes()))).unwrap().seal(SECRET);
+        let mut opener = Opener::from_identity(&format!("ed25519:{}", hex::encode(seed))).unwrap();
+        assert_eq!(opener.open(&sealed).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn empty_value_stays_empty() {
+        assert_eq!(Sealer::from_passphrase("correct horse").seal(""), "");
+    }
+
+    #[test]
+    fn wrong_passphrase_fails() {
+        let sealed = Sealer::from_passphrase("correct horse").seal(SECRET);
+        assert!(Opener::from_passphrase("battery staple").open(&sealed).is_err());
+    }
+
+    #[test]
+    fn wrong_identity_fails() {
+        let (_, public) = generate_identity();
+        let (other, _) = generate_identity();
+        let sealed = Sealer::from_recipient(&public).unwrap().seal(SECRET);
+        assert!(Opener::from_identity(&other).unwrap().open(&sealed).is_err());
+    }
+
+    #[test]
+    fn sealed_values_in_csv_and_json() {
+        let (private, public) = generate_identity();
+        let sealer = Sealer::from_recipient(&public).unwrap();
+        let (secret, seed) = (sealer.seal(SECRET), sealer.seal("word word"));
+        let csv = format!("2,0:00ff,EQ00,abcd,{},{},1234,1,,", secret, seed);
+        let json = format!("{{\"address\":\"0:00ff\",\"secret\":\"{}\",\"mnemonic\":\"{}\",\"rule\":1}}", secret, seed);
+        let mut opener = Opener::from_identity(&private).unwrap();
+        for line in &[csv, json] {
+            let found: Vec<&str> = find_sealed(line).iter().map(|&(start, end)| &line[start..end]).collect();
+            assert_eq!(found, vec![secret.as_str(), seed.as_str()]);
+            let opened = opener.open_all(line).unwrap();
+            assert_eq!(opened, line.replace(&secret, SECRET).replace(&seed, "word word"));
+        }
+    }
+}
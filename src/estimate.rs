@@ -0,0 +1,284 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::RngCore;
+use crate::settings::Settings;
+use crate::addr_gen::user_friendly::{FriendlyFormat, USER_FRIENDLY_LEN, user_friendly};
+use crate::addr_checker::BeautyAddressCheck;
+use crate::addr_checker::nibbles::ADDRESS_NIBBLES;
+use crate::addr_checker::patterns::{Pattern, PatternKind, PatternSet, AddressFormat};
+
+const CONFIDENCE_LEVELS: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Chance of a random address to match a plain pattern, `None` for regex and glob which
+/// have to be sampled.
+pub fn pattern_probability(pattern: &Pattern, format: FriendlyFormat, workchain: i8) -> Option<f64> {
+    let len = pattern.value.len();
+    let probability = match (pattern.format, pattern.kind) {
+        (_, PatternKind::Regex) | (_, PatternKind::Glob) => return None,
+        (AddressFormat::Hex, PatternKind::Contains) =>
+            (ADDRESS_NIBBLES - len + 1) as f64 * 16f64.powi(-(len as i32)),
+        (AddressFormat::Hex, _) => 16f64.powi(-(len as i32)),
+        (AddressFormat::Base64, PatternKind::Contains) | (AddressFormat::Base64Url, PatternKind::Contains) =>
+            (USER_FRIENDLY_LEN - len + 1) as f64 * 64f64.powi(-(len as i32)),
+        (AddressFormat::Base64, PatternKind::Suffix) | (AddressFormat::Base64Url, PatternKind::Suffix) =>
+            64f64.powi(-(len as i32)),
+        (address_format, PatternKind::Prefix) => {
+            let format = format.with_url_safe(address_format == AddressFormat::Base64Url);
+            friendly_prefix_probability(&pattern.value, format, workchain)
+        }
+    };
+    Some(probability.min(1.0))
+}
+
+/// First two chars of the user-friendly address are fixed by the flags and workchain, the third
+/// one has 4 fixed bits and 2 bits of the account hash.
+fn friendly_prefix_probability(prefix: &str, format: FriendlyFormat, workchain: i8) -> f64 {
+    let third_chars: Vec<u8> = (0..4u8)
+        .map(|top| {
+            let mut hash = [0u8; 32];
+            hash[0] = top << 6;
+            user_friendly(workchain, &hash, format).as_bytes()[2]
+        })
+        .collect();
+    let fixed = user_friendly(workchain, &[0u8; 32], format);
+    let mut probability = 1.0;
+    for (i, c) in prefix.bytes().enumerate() {
+        probability *= match i {
+            0 | 1 if fixed.as_bytes()[i] == c => 1.0,
+            0 | 1 => 0.0,
+            2 if third_chars.contains(&c) => 0.25,
+            2 => 0.0,
+            _ => 1.0 / 64.0,
+        };
+    }
+    probability
+}
+
+pub fn char_factor(format: AddressFormat) -> f64 {
+    match format {
+        AddressFormat::Hex => 16.0,
+        AddressFormat::Base64 | AddressFormat::Base64Url => 64.0,
+    }
+}
+
+/// Share of random account hashes the checker accepts.
+pub fn sample_probability(checker: &BeautyAddressCheck, samples: u64) -> (f64, u64) {
+    let mut rng = rand::thread_rng();
+    let mut hash = [0u8; 32];
+    let mut hits = 0;
+    for _ in 0..samples {
+        rng.fill_bytes(&mut hash);
+        if checker.check_raw(&hash).is_some() {
+            hits += 1;
+        }
+    }
+    (hits as f64 / samples as f64, hits)
+}
+
+/// Regex, glob patterns and beauty rules, checked together on random hashes.
+pub struct Sampled {
+    pub names: Vec<String>,
+    pub has_rules: bool,
+    pub probability: f64,
+    pub hits: u64,
+}
+
+/// Probabilities of the plain patterns and of the sampled rest.
+pub struct MatchEstimate {
+    pub computed: Vec<(Pattern, f64)>,
+    pub sampled: Option<Sampled>,
+}
+
+impl MatchEstimate {
+    /// Plain patterns are computed, regex, glob and beauty rules are sampled on `samples` random hashes.
+    pub fn new(settings: &Settings, samples: u64) -> Result<Self, String> {
+        let mut computed = vec![];
+        let mut sampled = vec![];
+        for pattern in settings.patterns.all() {
+            match pattern_probability(&pattern, settings.friendly_format, settings.workchain) {
+                Some(probability) => computed.push((pattern, probability)),
+                None => sampled.push(pattern),
+            }
+        }
+        let has_rules = settings.rules.rules.iter().any(|r| r.enabled) || settings.min_score.is_some();
+        if sampled.is_empty() && !has_rules {
+            return Ok(Self { computed, sampled: None });
+        }
+        let names = sampled.iter().map(|p| p.describe()).collect();
+        let checker = settings.checker_with(&settings.rules, PatternSet::from_patterns(sampled)?);
+        let (probability, hits) = sample_probability(&checker, samples);
+        Ok(Self { computed, sampled: Some(Sampled { names, has_rules, probability, hits }) })
+    }
+
+    /// Chance to match anything, patterns are taken as independent.
+    pub fn any(&self) -> f64 {
+        // log of the chance to miss every pattern, keeps precision for tiny probabilities
+        let miss_all: f64 = self.computed.iter()
+            .map(|(_, probability)| probability)
+            .chain(self.sampled.iter().map(|s| &s.probability))
+            .map(|probability| (-probability).ln_1p())
+            .sum();
+        -miss_all.exp_m1()
+    }
+}
+
+/// Chance of a random candidate to be saved.
+pub fn match_probability(settings: &Settings, samples: u64) -> Result<f64, String> {
+    Ok(MatchEstimate::new(settings, samples)?.any())
+}
+
+/// Candidates per second of all threads together, generated and checked the same way as in a real run.
+pub fn measure_rate(settings: &Settings, duration: Duration) -> Result<f64, String> {
+    // fail on a broken contract before starting threads
+    settings.generator_settings().generator()?;
+    let handles: Vec<_> = (0..settings.threads_amount)
+        .map(|_| {
+            let checker = settings.checker();
+            let generator_settings = settings.generator_settings();
+            let with_mnemonic = generator_settings.with_mnemonic;
+            thread::spawn(move || {
+                let mut generator = generator_settings.generator().unwrap();
+                let start = Instant::now();
+                let mut attempts: u64 = 0;
+                while start.elapsed() < duration {
+                    let candidate = if with_mnemonic {
+                        generator.candidate_from_random_seed()
+                    } else {
+                        generator.random_candidate()
+                    };
+                    checker.check_raw(&candidate.hash);
+                    attempts += 1;
+                }
+                attempts as f64 / start.elapsed().as_secs_f64()
+            })
+        })
+        .collect();
+    Ok(handles.into_iter().map(|h| h.join().unwrap()).sum())
+}
+
+/// Attempts needed to find at least one match with the given confidence.
+pub fn attempts_for(probability: f64, confidence: f64) -> f64 {
+    if probability >= 1.0 {
+        return 1.0;
+    }
+    (1.0 - confidence).ln() / (-probability).ln_1p()
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return "never".to_string();
+    }
+    let units = [("y", 365.0 * 86400.0), ("d", 86400.0), ("h", 3600.0), ("m", 60.0)];
+    for (i, (name, size)) in units.iter().enumerate() {
+        if seconds >= *size {
+            let whole = (seconds / size).floor();
+            if i == 0 {
+                return format!("{:.0}{}", whole, name);
+            }
+            let (next_name, next_size) = units.get(i + 1).copied().unwrap_or(("s", 1.0));
+            return format!("{:.0}{} {:.0}{}", whole, name, ((seconds - whole * size) / next_size).floor(), next_name);
+        }
+    }
+    format!("{:.1}s", seconds)
+}
+
+fn print_row(name: &str, probability: f64, rate: f64, char_factor: Option<f64>) {
+    let etas: Vec<String> = CONFIDENCE_LEVELS.iter()
+        .map(|c| format_duration(attempts_for(probability, *c) / rate))
+        .collect();
+    let extra_char = char_factor
+        .map(|f| format!("x{} ({})", f, format_duration(attempts_for(probability / f, 0.5) / rate)))
+        .unwrap_or_else(|| "-".to_string());
+    println!("{:<40} {:>12.3e} {:>14.3e} {:>12} {:>12} {:>12}   {}",
+             name, probability, 1.0 / probability, etas[0], etas[1], etas[2], extra_char);
+}
+
+pub fn run(settings: &Settings, duration: Duration, samples: u64) -> Result<(), String> {
+    println!("Measuring keys/sec for {}s...", duration.as_secs());
+    let rate = measure_rate(settings, duration)?;
+    println!("Keys/sec on this host: {:.0} ({} threads)", rate, settings.threads_amount);
+    println!();
+    println!("{:<40} {:>12} {:>14} {:>12} {:>12} {:>12}   +1 CHAR (ETA 50%)",
+             "PATTERN", "PROBABILITY", "ATTEMPTS", "ETA 50%", "ETA 90%", "ETA 99%");
+
+    let estimate = MatchEstimate::new(settings, samples)?;
+    for (pattern, probability) in &estimate.computed {
+        print_row(&pattern.describe(), *probability, rate, Some(char_factor(pattern.format)));
+    }
+    if let Some(sampled) = &estimate.sampled {
+        let mut name = if sampled.has_rules { "beauty rules".to_string() } else { String::new() };
+        if !sampled.names.is_empty() {
+            name = format!("{} {}", name, sampled.names.join(" ")).trim().to_string();
+        }
+        if sampled.hits == 0 {
+            println!("{:<40} no hits in {} sampled hashes, probability is below {:.3e}",
+                     name, samples, 1.0 / samples as f64);
+        } else {
+            print_row(&format!("{} (sampled, {} hits)", name, sampled.hits), sampled.probability, rate, None);
+        }
+    }
+
+    println!();
+    print_row("ANY", estimate.any(), rate, None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probability(line: &str, format: FriendlyFormat, workchain: i8) -> Option<f64> {
+        let pattern = PatternSet::parse(line).unwrap().all().remove(0);
+        pattern_probability(&pattern, format, workchain)
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() <= expected * 1e-12, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn hex_patterns() {
+        let format = FriendlyFormat::default();
+        assert_close(probability("prefix dead", format, 0), 16f64.powi(-4));
+        assert_close(probability("suffix 00000000", format, 0), 16f64.powi(-8));
+        assert_close(probability("contains ab", format, 0), 63.0 / 256.0);
+        assert_close(probability("contains 0", format, 0), 1.0);
+        assert_eq!(probability("regex ^dead", format, 0), None);
+        assert_eq!(probability("glob dead*", format, 0), None);
+    }
+
+    #[test]
+    fn user_friendly_patterns() {
+        let format = FriendlyFormat::default();
+        assert_close(probability("suffix:base64url T-n", format, 0), 64f64.powi(-3));
+        assert_close(probability("contains:base64 TON", format, 0), 46.0 * 64f64.powi(-3));
+        // bounceable basechain addresses start with "EQ", the third char is one of A-D
+        assert_close(probability("prefix:base64url EQ", format, 0), 1.0);
+        assert_close(probability("prefix:base64url EQB", format, 0), 0.25);
+        assert_close(probability("prefix:base64url EQBton", format, 0), 0.25 * 64f64.powi(-3));
+        assert_close(probability("prefix:base64url EQE", format, 0), 0.0);
+        assert_close(probability("prefix:base64url UQ", format, 0), 0.0);
+        // masterchain ones start with "Ef", the third char is one of 8, 9, - and _
+        assert_close(probability("prefix:base64url Ef8", format, -1), 0.25);
+        let non_bounceable = FriendlyFormat { bounceable: false, ..format };
+        assert_close(probability("prefix:base64url UQA", non_bounceable, 0), 0.25);
+        assert_close(probability("prefix:base64url EQA", non_bounceable, 0), 0.0);
+    }
+
+    #[test]
+    fn any_of_independent_patterns() {
+        let patterns = PatternSet::parse("prefix 0\nsuffix 1").unwrap().all();
+        let mut estimate = MatchEstimate { computed: patterns.into_iter().map(|p| (p, 0.5)).collect(), sampled: None };
+        assert!((estimate.any() - 0.75).abs() < 1e-12);
+        estimate.sampled = Some(Sampled { names: vec![], has_rules: true, probability: 0.5, hits: 10 });
+        assert!((estimate.any() - 0.875).abs() < 1e-12);
+    }
+
+    #[test]
+    fn attempts() {
+        assert_eq!(attempts_for(1.0, 0.99), 1.0);
+        assert!((attempts_for(0.5, 0.75) - 2.0).abs() < 1e-9);
+        assert!((attempts_for(1e-9, 0.5) - 2f64.ln() * 1e9).abs() < 1.0);
+    }
+}
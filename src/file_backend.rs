@@ -1,21 +1,34 @@
-// use std::sync::{Arc, Mutex};
-// use postgres::{Connection, TlsMode};
-// use postgres::types::{ToSql, INT4, VARCHAR, Type};
-// use postgres_binary_copy::BinaryCopyReader;
-// use streaming_iterator::StreamingIterator;
 use csv::Writer;
-use std::fs::File;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::addr_gen::Account;
+use crate::addr_gen::user_friendly::FriendlyFormat;
+use crate::addr_checker::BeautyMatch;
+use crate::crypto::Sealer;
+use crate::file_backend::redis_connector::RedisConnector;
+use crate::file_backend::postgresql_connector::PostgreSQLConnector;
+use crate::file_backend::sqlite_connector::SqliteConnector;
+use crate::file_backend::jsonl_connector::JsonlConnector;
+
+pub mod redis_connector;
+pub mod postgresql_connector;
+pub mod sqlite_connector;
+pub mod jsonl_connector;
+pub mod sealed_connector;
 
 pub mod backend_types {
     pub const CSV: &str = "csv";
     pub const REDIS: &str = "redis";
     pub const POSTGRESQL: &str = "postgresql";
+    pub const SQLITE: &str = "sqlite";
+    pub const JSONL: &str = "jsonl";
 }
 
 pub struct FileBackend {
     path: String,
     backend_type: String,
+    append: bool,
 }
 
 impl FileBackend {
@@ -23,127 +36,321 @@ impl FileBackend {
         let backend_type;
         if path.starts_with("redis") {
             backend_type = backend_types::REDIS;
-            // } else if path.starts_with("postgresql") {
-            //     backend_type = backend_types::POSTGRESQL;
+        } else if path.starts_with("postgresql") || path.starts_with("postgres://") {
+            backend_type = backend_types::POSTGRESQL;
+        } else if path.starts_with("sqlite:") || path.ends_with(".db") {
+            backend_type = backend_types::SQLITE;
+        } else if path.ends_with(".jsonl") {
+            backend_type = backend_types::JSONL;
         } else {
             backend_type = backend_types::CSV;
         }
         Self {
             path: path.to_string(),
             backend_type: backend_type.to_string(),
+            append: false,
         }
     }
 
-    pub fn get_connector(&self) -> Box<dyn Connector> {
-        match self.backend_type.as_ref() {
-            backend_types::CSV => Box::new(CsvConnector::from_path(&self.path).unwrap()),
-            backend_types::REDIS => Box::new(CsvConnector::from_path(&self.path).unwrap()),
-            // backend_types::POSTGRESQL => Box::new(PostgreSQLConnector::from_connection_string(&self.path).unwrap()),
-            _ => Box::new(CsvConnector::from_path(&self.path).unwrap()),
+    /// Overrides the format detected from the path, for files only.
+    pub fn with_format(mut self, format: &str) -> Result<Self, String> {
+        if self.backend_type == backend_types::REDIS || self.backend_type == backend_types::POSTGRESQL {
+            return Err(format!("--format is for files only, not {} urls", self.backend_type));
         }
+        self.backend_type = format.to_string();
+        Ok(self)
+    }
+
+    /// Keeps the results of a previous run in csv and jsonl files, e.g. on resume.
+    pub fn with_append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn get_connector(&self) -> Result<Box<dyn Connector>, String> {
+        Ok(match self.backend_type.as_ref() {
+            backend_types::CSV => Box::new(CsvConnector::from_path(&self.path, self.append)?),
+            backend_types::REDIS => Box::new(RedisConnector::from_url(&self.path)?),
+            backend_types::POSTGRESQL => Box::new(PostgreSQLConnector::from_connection_string(&self.path)?),
+            backend_types::SQLITE => Box::new(SqliteConnector::from_path(&self.path)?),
+            backend_types::JSONL => Box::new(JsonlConnector::from_path(&self.path, self.append)?),
+            _ => Box::new(CsvConnector::from_path(&self.path, self.append)?),
+        })
     }
 }
 
+/// A found address with every field formatted for saving, secret and seed are sealed when
+/// encryption is on.
+#[derive(Clone)]
+pub struct Record {
+    pub address: String,
+    pub user_friendly: String,
+    pub workchain: i8,
+    pub public: String,
+    pub secret: String,
+    pub seed: String,
+    pub derivation_path: Option<&'static str>,
+    pub contract_hash: String,
+    pub rule: u8,
+    pub pattern: String,
+    pub score: Option<u32>,
+    pub timestamp: u64,
+    /// Worker thread and key index in incremental mode with checkpoints, not saved.
+    pub position: Option<(usize, u64)>,
+}
 
-pub trait Connector: Send + Sync {
-    fn save(&mut self) {}
-    fn push(&mut self, item: Account, rule: u8) {}
+impl Record {
+    /// `format` is the one patterns are matched against, so a base64 hit shows its pattern.
+    pub fn new(item: Account, hit: &BeautyMatch, format: FriendlyFormat, sealer: Option<&Sealer>) -> Self {
+        let secret = item.secret_as_string();
+        let (secret, seed) = match sealer {
+            Some(sealer) => (sealer.seal(&secret), sealer.seal(&item.seed)),
+            None => (secret, item.seed.clone()),
+        };
+        Self {
+            address: item.address(),
+            user_friendly: item.user_friendly(format),
+            workchain: item.workchain,
+            public: item.public_as_string(),
+            secret,
+            seed,
+            derivation_path: item.derivation_path(),
+            contract_hash: item.contract_hash,
+            rule: hit.rule,
+            pattern: hit.pattern.clone(),
+            score: hit.score,
+            timestamp: unix_timestamp(),
+            position: None,
+        }
+    }
+
+    /// Named fields, for connectors which store records as key/value.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("address", self.address.clone()),
+            ("user_friendly", self.user_friendly.clone()),
+            ("public", self.public.clone()),
+            ("secret", self.secret.clone()),
+            ("seed", self.seed.clone()),
+            ("contract_hash", self.contract_hash.clone()),
+            ("rule", self.rule.to_string()),
+            ("pattern", self.pattern.clone()),
+            ("score", self.score.map(|s| s.to_string()).unwrap_or_default()),
+        ]
+    }
 }
 
-pub struct RedisConnector {
-    connection: String,
-    data_buffer: Vec<Vec<String>>,
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Delay before the next save attempt of a connector whose storage is down, doubled after every
+/// failure. Saves come on the writer's flush interval, so a down storage never makes them sleep.
+pub struct Backoff {
+    delay: Duration,
+    next: Option<Instant>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    const MIN: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        Self { delay: Self::MIN, next: None }
+    }
+
+    /// Whether the next attempt is due.
+    pub fn ready(&self) -> bool {
+        self.next.map_or(true, |next| Instant::now() >= next)
+    }
+
+    /// Schedules the next attempt and returns the delay before it.
+    pub fn failed(&mut self) -> Duration {
+        let delay = self.delay;
+        self.next = Some(Instant::now() + delay);
+        self.delay = (delay * 2).min(Self::MAX);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        self.delay = Self::MIN;
+        self.next = None;
+    }
 }
 
+/// Takes connector options (`name=value` query params listed in `names`) out of a connection
+/// string, other query params are left for the driver.
+pub fn split_options(url: &str, names: &[&str]) -> (String, HashMap<String, String>) {
+    let mut parts = url.splitn(2, '?');
+    let base = parts.next().unwrap_or("").to_string();
+    let mut options = HashMap::new();
+    let mut rest = vec![];
+    for param in parts.next().unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+        let mut name_value = param.splitn(2, '=');
+        let name = name_value.next().unwrap_or("");
+        match name_value.next() {
+            Some(value) if names.contains(&name) => {
+                options.insert(name.to_string(), value.to_string());
+            }
+            _ => rest.push(param),
+        }
+    }
+    if rest.is_empty() {
+        (base, options)
+    } else {
+        (format!("{}?{}", base, rest.join("&")), options)
+    }
+}
+
+
+pub trait Connector: Send {
+    fn save(&mut self) {}
+    fn push(&mut self, record: Record) {}
+    /// Failed saves so far, records of a failed save are kept for the next one.
+    fn errors(&self) -> u64 { 0 }
+    /// Buffer is at its limit while saves fail, pushed records would not fit.
+    fn is_full(&self) -> bool { false }
+}
+
+/// Columns of `CsvConnector` rows, bumped with `CSV_SCHEMA_VERSION` on every change.
+/// Version 1 rows had no header: `account_id, "pub|secret", seed, tvc, rule`.
+pub const CSV_SCHEMA_VERSION: &str = "2";
+pub const CSV_HEADER: [&str; 10] = [
+    "schema_version", "address", "user_friendly", "public", "secret", "seed", "contract_hash", "rule", "pattern", "score",
+];
+
 pub struct CsvConnector {
     writer: Writer<File>,
     data_buffer: Vec<Vec<String>>,
-    max_data_buffer: usize
+    max_data_buffer: usize,
+    errors: u64,
 }
 
 impl CsvConnector {
-    pub fn from_path(path: &str) -> Result<Self, String> {
-        let writer = Writer::from_path(path)
+    /// The header is written to a new or truncated file, appending to a file with rows keeps it.
+    pub fn from_path(path: &str, append: bool) -> Result<Self, String> {
+        let file = OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(path)
             .map_err(|e| format!("unable open csv file: {}", e))?;
-        Ok(Self { writer, data_buffer: vec![] , max_data_buffer: 1000})
+        let empty = file.metadata()
+            .map_err(|e| format!("unable open csv file: {}", e))?
+            .len() == 0;
+        let mut writer = Writer::from_writer(file);
+        if empty {
+            writer.write_record(CSV_HEADER)
+                .map_err(|e| format!("unable to write csv header: {}", e))?;
+            writer.flush()
+                .map_err(|e| format!("unable to write csv header: {}", e))?;
+        }
+        Ok(Self { writer, data_buffer: vec![], max_data_buffer: 1000, errors: 0 })
+    }
+
+    /// Rows taken by the writer are dropped from `data_buffer` even if the flush fails: they
+    /// stay in its buffer for the next flush, writing them again would duplicate them.
+    fn write(&mut self) -> Result<(), String> {
+        let mut written = 0;
+        let mut result = Ok(());
+        for row in &self.data_buffer {
+            if let Err(e) = self.writer.write_record(row) {
+                result = Err(e.to_string());
+                break;
+            }
+            written += 1;
+        }
+        self.data_buffer.drain(..written);
+        result?;
+        self.writer.flush().map_err(|e| e.to_string())
     }
 }
 
 impl Connector for CsvConnector {
     fn save(&mut self) {
-        for row in &mut self.data_buffer {
-            self.writer.write_record(row).unwrap();
+        // runs with an empty buffer too, the writer may hold rows of a failed flush
+        if let Err(e) = self.write() {
+            println!("unable to write csv file: {}, unsaved rows are kept for the next save", e);
+            self.errors += 1;
         }
-        self.writer.flush().unwrap();
-        self.data_buffer.clear();
     }
 
-    fn push(&mut self, item: Account, rule: u8) {
-        if self.data_buffer.len() > self.max_data_buffer{
+    fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    fn push(&mut self, record: Record) {
+        if self.data_buffer.len() >= self.max_data_buffer{
             self.save();
         }
-        let keys = format!("{}|{}", item.public_as_string(), item.secret_as_string());
         self.data_buffer.push(vec![
-            item.account_id,
-            keys,
-            item.seed,
-            format!("{}", item.tvc),
-            format!("{}", rule),
+            CSV_SCHEMA_VERSION.to_string(),
+            record.address,
+            record.user_friendly,
+            record.public,
+            record.secret,
+            record.seed,
+            record.contract_hash,
+            format!("{}", record.rule),
+            record.pattern,
+            record.score.map(|s| s.to_string()).unwrap_or_default(),
         ])
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
 
-// pub struct PostgreSQLConnector {
-//     connection: Connection,
-//     data_buffer: Vec<Box<dyn ToSql + Send + 'static>>,
-//     max_buffer_size: usize,
-// }
-//
-// impl PostgreSQLConnector {
-//     const COPY_QUERY: &'static str = "COPY vanity_beauty_test (id, keys, seed, tvc, rule) FROM STDIN (FORMAT binary)";
-//
-//
-//     pub fn from_connection_string(string: &str) -> Result<Self, String> {
-//         let connection = Connection::connect(string, TlsMode::None)
-//             .map_err(|e| format!("unable to connect to PostgreSQL: {}", e))?;
-//         connection.execute("create table vanity_beauty_test
-//                                 (
-//                                     id   varchar,
-//                                     keys varchar,
-//                                     keys varchar,
-//                                     tvc  integer,
-//                                     rule integer
-//                                 );", &[]).map_err(|e| format!("unable to create table: {}", e))?;
-//         connection.execute("create index vanity_beauty_test_id_index on vanity_beauty_test (id);", &[])
-//             .map_err(|e| format!("unable to create index: {}", e))?;
-//         Ok(Self {connection, data_buffer: vec![], max_buffer_size: 10 })
-//     }
-// }
-//
-// impl Connector for PostgreSQLConnector {
-//     fn save(&mut self) {
-//         let types = &[VARCHAR, VARCHAR, VARCHAR, INT4, INT4];
-//         let mut data_buffer: Vec<Box<ToSql>> = vec![];
-//         std::mem::swap(&mut data_buffer, &mut self.data_buffer);
-//         let data = streaming_iterator::convert(data_buffer.into_iter()).map_ref(|v| &**v);
-//         let mut reader = BinaryCopyReader::new(types, data);
-//         let stmt = self.connection.prepare(Self::COPY_QUERY).unwrap();
-//         stmt.copy_in(&[], &mut reader).unwrap();
-//     }
-//
-//     fn push(&mut self, item: Account, rule: u8) {
-//         if self.data_buffer.len() >= self.max_buffer_size {
-//             self.save();
-//         }
-//         let keys = format!("{}|{}", item.public_as_string(), item.secret_as_string());
-//         let mut account_data: Vec<Box<ToSql>> = vec![
-//             Box::new(item.account_id),
-//             Box::new(keys),
-//             Box::new(item.seed),
-//             Box::new(item.tvc as i32),
-//             Box::new(rule as i32)
-//         ];
-//         self.data_buffer.append(&mut account_data);
-//     }
-// }
+    fn record(rule: u8, score: Option<u32>) -> Record {
+        Record {
+            address: "0:83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8".to_string(),
+            user_friendly: "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N".to_string(),
+            workchain: 0,
+            public: "a1b2".to_string(),
+            secret: "c3d4".to_string(),
+            seed: "word, word".to_string(),
+            derivation_path: None,
+            contract_hash: "e2b6".to_string(),
+            rule,
+            pattern: "prefix:83df".to_string(),
+            score,
+            timestamp: 1600000000,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn csv_columns() {
+        let path = std::env::temp_dir().join(format!("address_gen_{}_hits.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut connector = CsvConnector::from_path(path, false).unwrap();
+        connector.push(record(10, None));
+        connector.save();
+        // appending to a file with rows doesn't repeat the header
+        let mut connector = CsvConnector::from_path(path, true).unwrap();
+        connector.push(record(20, Some(42)));
+        connector.save();
+
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        let header: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        let rows: Vec<Vec<String>> = reader.records().map(|r| r.unwrap().iter().map(String::from).collect()).collect();
+        fs::remove_file(path).ok();
+        assert_eq!(header, CSV_HEADER);
+        assert_eq!(header, vec!["schema_version", "address", "user_friendly", "public", "secret", "seed",
+                                "contract_hash", "rule", "pattern", "score"]);
+        assert_eq!(rows, vec![
+            vec!["2", "0:83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8",
+                 "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N", "a1b2", "c3d4", "word, word", "e2b6", "10",
+                 "prefix:83df", ""],
+            vec!["2", "0:83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8",
+                 "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N", "a1b2", "c3d4", "word, word", "e2b6", "20",
+                 "prefix:83df", "42"],
+        ]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use serde::Serialize;
+use crate::file_backend::{Connector, Record};
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    address: &'a str,
+    workchain: i8,
+    public: &'a str,
+    secret: &'a str,
+    mnemonic: Option<&'a str>,
+    derivation_path: Option<&'static str>,
+    contract_hash: &'a str,
+    rule: u8,
+    pattern: &'a str,
+    score: Option<u32>,
+    timestamp: u64,
+}
+
+/// One JSON object per line for every hit.
+pub struct JsonlConnector {
+    writer: BufWriter<File>,
+    data_buffer: Vec<String>,
+    max_data_buffer: usize,
+    errors: u64,
+}
+
+impl JsonlConnector {
+    pub fn from_path(path: &str, append: bool) -> Result<Self, String> {
+        let file = OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(path)
+            .map_err(|e| format!("unable to open jsonl file: {}", e))?;
+        Ok(Self { writer: BufWriter::new(file), data_buffer: vec![], max_data_buffer: 1000, errors: 0 })
+    }
+
+    /// Lines taken by the writer are dropped from `data_buffer` even if the flush fails: they
+    /// stay in its buffer for the next flush, writing them again would duplicate them.
+    fn write(&mut self) -> std::io::Result<()> {
+        let mut written = 0;
+        let mut result = Ok(());
+        for line in &self.data_buffer {
+            if let Err(e) = self.writer.write_all(line.as_bytes()) {
+                result = Err(e);
+                break;
+            }
+            written += 1;
+        }
+        self.data_buffer.drain(..written);
+        result?;
+        self.writer.flush()
+    }
+}
+
+impl Connector for JsonlConnector {
+    fn save(&mut self) {
+        // runs with an empty buffer too, the writer may hold lines of a failed flush
+        if let Err(e) = self.write() {
+            println!("unable to write jsonl file: {}, unsaved rows are kept for the next save", e);
+            self.errors += 1;
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    fn push(&mut self, record: Record) {
+        if self.data_buffer.len() >= self.max_data_buffer {
+            self.save();
+        }
+        let json = JsonRecord {
+            address: &record.address,
+            workchain: record.workchain,
+            public: &record.public,
+            secret: &record.secret,
+            mnemonic: if record.seed.is_empty() { None } else { Some(record.seed.as_str()) },
+            derivation_path: record.derivation_path,
+            contract_hash: &record.contract_hash,
+            rule: record.rule,
+            pattern: &record.pattern,
+            score: record.score,
+            timestamp: record.timestamp,
+        };
+        // plain strings and numbers always serialize
+        let mut line = serde_json::to_string(&json).unwrap();
+        // one write per line, so a failed write never leaves half a line behind
+        line.push('\n');
+        self.data_buffer.push(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    fn record(pattern: &str) -> Record {
+        Record {
+            address: "0:83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8".to_string(),
+            user_friendly: "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N".to_string(),
+            workchain: 0,
+            public: "a1b2".to_string(),
+            secret: "c3d4".to_string(),
+            seed: String::new(),
+            derivation_path: None,
+            contract_hash: "e2b6".to_string(),
+            rule: 10,
+            pattern: pattern.to_string(),
+            score: None,
+            timestamp: 1600000000,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn appends_lines() {
+        let path = std::env::temp_dir().join(format!("address_gen_{}_hits.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut connector = JsonlConnector::from_path(path, false).unwrap();
+        connector.push(record("prefix:83df"));
+        connector.save();
+        connector.push(record("suffix:31a8"));
+        connector.save();
+        connector.save();
+        let mut connector = JsonlConnector::from_path(path, true).unwrap();
+        connector.push(record("contains:ec75"));
+        connector.save();
+
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).ok();
+        let patterns: Vec<String> = content.lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["pattern"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(patterns, vec!["prefix:83df", "suffix:31a8", "contains:ec75"]);
+        assert!(content.contains(r#""mnemonic":null"#));
+        assert_eq!(connector.errors(), 0);
+    }
+
+    /// Lines of a failed flush stay in the writer, so they are neither written twice nor dropped.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_flush_keeps_lines_once() {
+        let mut connector = JsonlConnector::from_path("/dev/full", true).unwrap();
+        connector.push(record("prefix:83df"));
+        connector.push(record("suffix:31a8"));
+        connector.save();
+        assert_eq!(connector.errors(), 1);
+        assert!(connector.data_buffer.is_empty());
+        let pending = connector.writer.buffer().len();
+        assert!(pending > 0);
+        connector.save();
+        assert_eq!(connector.errors(), 2);
+        assert_eq!(connector.writer.buffer().len(), pending);
+    }
+}
@@ -0,0 +1,160 @@
+use redis::{Client, Connection, RedisResult};
+use crate::file_backend::{Backoff, Connector, Record, split_options};
+
+struct RedisRecord {
+    address: String,
+    rule: u8,
+    fields: Vec<(&'static str, String)>,
+}
+
+/// Key templates: `{address}` in `address_key` and `{rule}` in `rule_key` are replaced with the
+/// values of the hit.
+struct RedisKeys {
+    address_key: String,
+    rule_key: String,
+}
+
+impl RedisKeys {
+    /// Takes the key options out of `url`, returns the url left for the driver.
+    fn from_url(url: &str) -> (String, Self) {
+        let (url, options) = split_options(url, &["prefix", "address_key", "rule_key"]);
+        let prefix = options.get("prefix").cloned().unwrap_or_else(|| "vanity".to_string());
+        let address_key = options.get("address_key").cloned()
+            .unwrap_or_else(|| format!("{}:address:{{address}}", prefix));
+        let rule_key = options.get("rule_key").cloned()
+            .unwrap_or_else(|| format!("{}:rule:{{rule}}", prefix));
+        (url, Self { address_key, rule_key })
+    }
+
+    fn address_key(&self, record: &RedisRecord) -> String {
+        self.address_key.replace("{address}", &record.address)
+    }
+
+    fn rule_key(&self, record: &RedisRecord) -> String {
+        self.rule_key.replace("{rule}", &record.rule.to_string())
+    }
+}
+
+/// Every hit is a hash under `address_key` and its address is added to the set under `rule_key`.
+/// Keys are templates set by URL options, e.g.
+/// `redis://127.0.0.1/0?prefix=vanity` or `redis://127.0.0.1/0?address_key=a:{address}&rule_key=r:{rule}`.
+pub struct RedisConnector {
+    client: Client,
+    connection: Option<Connection>,
+    keys: RedisKeys,
+    data_buffer: Vec<RedisRecord>,
+    backoff: Backoff,
+    errors: u64,
+}
+
+impl RedisConnector {
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        let (url, keys) = RedisKeys::from_url(url);
+        let client = Client::open(url.as_str())
+            .map_err(|e| format!("unable to parse redis url: {}", e))?;
+        let connection = client.get_connection()
+            .map_err(|e| format!("unable to connect to redis: {}", e))?;
+        Ok(Self {
+            client,
+            connection: Some(connection),
+            keys,
+            data_buffer: vec![],
+            backoff: Backoff::new(),
+            errors: 0,
+        })
+    }
+
+    fn write(&mut self) -> RedisResult<()> {
+        if self.connection.is_none() {
+            self.connection = Some(self.client.get_connection()?);
+        }
+        let mut pipe = redis::pipe();
+        for record in &self.data_buffer {
+            pipe.hset_multiple(self.keys.address_key(record), &record.fields[..]).ignore();
+            pipe.sadd(self.keys.rule_key(record), &record.address).ignore();
+        }
+        pipe.query(self.connection.as_mut().unwrap())
+    }
+}
+
+impl Connector for RedisConnector {
+    /// Reconnects on the next save after a failure, saves before the backoff delay fail without
+    /// trying. Records stay in the buffer until redis is back.
+    fn save(&mut self) {
+        if self.data_buffer.is_empty() {
+            return;
+        }
+        if !self.backoff.ready() {
+            self.errors += 1;
+            return;
+        }
+        match self.write() {
+            Ok(()) => {
+                self.data_buffer.clear();
+                self.backoff.succeeded();
+            }
+            Err(e) => {
+                self.connection = None;
+                let delay = self.backoff.failed();
+                println!("unable to write to redis: {}, keeping {} records, next attempt in {}s",
+                         e, self.data_buffer.len(), delay.as_secs());
+                self.errors += 1;
+            }
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    fn push(&mut self, record: Record) {
+        self.data_buffer.push(RedisRecord {
+            fields: record.fields(),
+            address: record.address,
+            rule: record.rule,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> RedisRecord {
+        RedisRecord { address: "0:83df".to_string(), rule: 12, fields: vec![] }
+    }
+
+    fn keys(url: &str) -> (String, String, String) {
+        let (url, keys) = RedisKeys::from_url(url);
+        (url, keys.address_key(&record()), keys.rule_key(&record()))
+    }
+
+    #[test]
+    fn default_keys() {
+        assert_eq!(keys("redis://127.0.0.1/0"),
+                   ("redis://127.0.0.1/0".to_string(), "vanity:address:0:83df".to_string(), "vanity:rule:12".to_string()));
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(keys("redis://127.0.0.1/0?prefix=ton"),
+                   ("redis://127.0.0.1/0".to_string(), "ton:address:0:83df".to_string(), "ton:rule:12".to_string()));
+    }
+
+    #[test]
+    fn templates() {
+        let (url, address_key, rule_key) = keys("redis://127.0.0.1/0?address_key=a:{address}&prefix=ton&rule_key=r{rule}");
+        assert_eq!(url, "redis://127.0.0.1/0");
+        assert_eq!((address_key.as_str(), rule_key.as_str()), ("a:0:83df", "r12"));
+        // a template without placeholders is used as is, the prefix only shapes the default templates
+        let (_, address_key, rule_key) = keys("redis://127.0.0.1/0?rule_key=hits");
+        assert_eq!((address_key.as_str(), rule_key.as_str()), ("vanity:address:0:83df", "hits"));
+    }
+
+    #[test]
+    fn driver_params_are_kept() {
+        let (url, _, rule_key) = keys("redis://127.0.0.1/0?timeout=5&prefix=ton&protocol=resp3");
+        assert_eq!(url, "redis://127.0.0.1/0?timeout=5&protocol=resp3");
+        assert_eq!(rule_key, "ton:rule:12");
+    }
+}
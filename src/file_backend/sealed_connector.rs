@@ -0,0 +1,116 @@
+use serde::Serialize;
+use crate::crypto::Sealer;
+use crate::file_backend::{Connector, Record};
+
+#[derive(Serialize)]
+struct SealedAccount<'a> {
+    address: &'a str,
+    public: &'a str,
+    secret: &'a str,
+    mnemonic: Option<&'a str>,
+    derivation_path: Option<&'static str>,
+}
+
+/// Write-only mode: key material of every record is sealed to the recipient key as one value in
+/// the `secret` field before it reaches the wrapped connector, `public` and `seed` are left empty.
+/// Address, rule, pattern and score stay in the clear to be searchable.
+pub struct SealedConnector {
+    inner: Box<dyn Connector>,
+    sealer: Sealer,
+}
+
+impl SealedConnector {
+    pub fn new(inner: Box<dyn Connector>, recipient: &str) -> Result<Self, String> {
+        Ok(Self { inner, sealer: Sealer::from_recipient(recipient)? })
+    }
+}
+
+impl Connector for SealedConnector {
+    fn save(&mut self) {
+        self.inner.save()
+    }
+
+    fn errors(&self) -> u64 {
+        self.inner.errors()
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    fn push(&mut self, mut record: Record) {
+        let account = SealedAccount {
+            address: &record.address,
+            public: &record.public,
+            secret: &record.secret,
+            mnemonic: if record.seed.is_empty() { None } else { Some(record.seed.as_str()) },
+            derivation_path: record.derivation_path,
+        };
+        // plain strings always serialize
+        let sealed = self.sealer.seal(&serde_json::to_string(&account).unwrap());
+        record.secret = sealed;
+        record.public = String::new();
+        record.seed = String::new();
+        record.derivation_path = None;
+        self.inner.push(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::addr_gen::HD_PATH;
+    use crate::crypto::{generate_identity, Opener};
+    use super::*;
+
+    struct Saved(Arc<Mutex<Vec<Record>>>);
+
+    impl Connector for Saved {
+        fn push(&mut self, record: Record) {
+            self.0.lock().unwrap().push(record);
+        }
+    }
+
+    fn record() -> Record {
+        Record {
+            address: "0:83dfd552e63729b472fcbcc8c45ebcc6691702558b68ec7527e1ba403a0f31a8".to_string(),
+            user_friendly: "EQCD39VS5jcptHL8vMjEXrzGaRcCVYto7HUn4bpAOg8xqB2N".to_string(),
+            workchain: 0,
+            public: "a1b2".to_string(),
+            secret: "c3d4".to_string(),
+            seed: "word word".to_string(),
+            derivation_path: Some(HD_PATH),
+            contract_hash: "e2b6".to_string(),
+            rule: 10,
+            pattern: "prefix:83df".to_string(),
+            score: Some(42),
+            timestamp: 1600000000,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn seals_key_material_only() {
+        let (identity, recipient) = generate_identity();
+        let saved = Arc::new(Mutex::new(vec![]));
+        let mut connector = SealedConnector::new(Box::new(Saved(saved.clone())), &recipient).unwrap();
+        connector.push(record());
+
+        let (sealed, original) = (&saved.lock().unwrap()[0], record());
+        assert_eq!(sealed.address, original.address);
+        assert_eq!(sealed.user_friendly, original.user_friendly);
+        assert_eq!(sealed.contract_hash, original.contract_hash);
+        assert_eq!((sealed.rule, &sealed.pattern, sealed.score), (original.rule, &original.pattern, original.score));
+        assert_eq!((sealed.public.as_str(), sealed.seed.as_str(), sealed.derivation_path), ("", "", None));
+
+        let opened = Opener::from_identity(&identity).unwrap().open(&sealed.secret).unwrap();
+        let account: serde_json::Value = serde_json::from_str(&opened).unwrap();
+        assert_eq!(account, serde_json::json!({
+            "address": original.address,
+            "public": "a1b2",
+            "secret": "c3d4",
+            "mnemonic": "word word",
+            "derivation_path": HD_PATH,
+        }));
+    }
+}
@@ -0,0 +1,91 @@
+use rusqlite::{Connection, params};
+use crate::file_backend::{Connector, Record};
+
+/// Hits are inserted into the `addresses` table, one transaction per batch of `max_data_buffer`
+/// rows. Path is `sqlite:<file>` or any file with a `.db` extension.
+pub struct SqliteConnector {
+    connection: Connection,
+    data_buffer: Vec<Record>,
+    max_data_buffer: usize,
+    errors: u64,
+}
+
+impl SqliteConnector {
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let path = path.trim_start_matches("sqlite:").trim_start_matches("//");
+        let connection = Connection::open(path)
+            .map_err(|e| format!("unable to open sqlite database: {}", e))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS addresses
+            (
+                id            INTEGER PRIMARY KEY,
+                address       TEXT    NOT NULL,
+                user_friendly TEXT    NOT NULL,
+                public        TEXT    NOT NULL,
+                secret        TEXT    NOT NULL,
+                seed          TEXT    NOT NULL,
+                contract_hash TEXT    NOT NULL,
+                rule          INTEGER NOT NULL,
+                pattern       TEXT    NOT NULL,
+                score         INTEGER,
+                created_at    INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS addresses_address_index ON addresses (address);
+            CREATE INDEX IF NOT EXISTS addresses_rule_index ON addresses (rule);"
+        ).map_err(|e| format!("unable to create table: {}", e))?;
+        Ok(Self { connection, data_buffer: vec![], max_data_buffer: 1000, errors: 0 })
+    }
+
+    fn insert(&mut self) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut stmt = transaction.prepare_cached(
+                "INSERT INTO addresses
+                (address, user_friendly, public, secret, seed, contract_hash, rule, pattern, score, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"
+            )?;
+            for row in &self.data_buffer {
+                stmt.execute(params![
+                    row.address,
+                    row.user_friendly,
+                    row.public,
+                    row.secret,
+                    row.seed,
+                    row.contract_hash,
+                    row.rule,
+                    row.pattern,
+                    row.score,
+                    row.timestamp as i64,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+impl Connector for SqliteConnector {
+    fn save(&mut self) {
+        if self.data_buffer.is_empty() {
+            return;
+        }
+        match self.insert() {
+            Ok(()) => self.data_buffer.clear(),
+            Err(e) => {
+                println!("unable to write to sqlite: {}, keeping {} rows until the next save",
+                         e, self.data_buffer.len());
+                self.errors += 1;
+            }
+        }
+    }
+
+    fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    fn push(&mut self, record: Record) {
+        if self.data_buffer.len() >= self.max_data_buffer {
+            self.save();
+        }
+        self.data_buffer.push(record);
+    }
+}
@@ -1,16 +1,36 @@
 pub mod addr_gen;
 pub mod addr_checker;
 pub mod file_backend;
+pub mod settings;
+pub mod estimate;
+pub mod stop;
+pub mod crypto;
+pub mod writer;
+pub mod stats;
+pub mod metrics;
+pub mod checkpoint;
 
 
 extern crate streaming_iterator;
 
-use std::time::Instant;
-use std::{thread, iter, time};
-use clap::{Arg, App};
+use std::time::Duration;
+use std::{process, thread, iter, time};
+use std::io::{self, BufRead, Write};
+use clap::{Arg, App, ArgMatches, SubCommand};
 use crate::addr_checker::BeautyAddressCheck;
-use crate::file_backend::{FileBackend, Connector};
-use std::sync::{Mutex, Arc};
+use crate::addr_checker::score::Leaderboard;
+use crate::settings::{Settings, GeneratorSettings};
+use crate::stop::{StopConditions, StopState};
+use crate::file_backend::{FileBackend, Connector, Record};
+use crate::file_backend::sealed_connector::SealedConnector;
+use crate::file_backend::jsonl_connector::JsonlConnector;
+use crate::crypto::{Sealer, Opener, PASSPHRASE_ENV};
+use std::sync::Arc;
+use crate::writer::{ResultWriter, HitSender, Fallback};
+use crate::stats::{Stats, Reporter};
+use crate::checkpoint::{Checkpoint, Progress};
+use crate::addr_gen::{AccountGenerator, AccountSecret};
+use crate::addr_gen::incremental::IncrementalKeys;
 
 #[allow(unused_variables)]
 pub fn main() -> Result<(), String> {
@@ -19,95 +39,481 @@ pub fn main() -> Result<(), String> {
         .author("Pavel @get_username")
         .about("Generating addresses for Free TON with random mnemonic \
                 or just random secret, checking, saving(file, redis, postgresql)")
-        .arg(Arg::with_name("mnemonic")
-            .short("m")
-            .long("mnemonic")
-            .help("Set true if you need to addresses with random mnemonic seed(much slower if set true)")
-            .takes_value(true))
-        .arg(Arg::with_name("contract_path")
-            .short("c")
-            .long("contract")
-            .help("Path to tvc file with contract for which addresses will be generated")
-            .takes_value(true))
-        .arg(Arg::with_name("threads_amount")
-            .short("t")
-            .long("threads")
-            .help("Amount of threads")
-            .takes_value(true))
+        .args(&settings::search_args())
         .arg(Arg::with_name("file_backend")
             .short("f")
             .long("file")
             .help("Path to file where to save results, redis or postgresql connection string")
             .takes_value(true))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .help("Format of the results file, detected by the file extension if not set")
+            .takes_value(true)
+            .possible_values(&["csv", "jsonl"]))
+        .arg(Arg::with_name("stats_interval")
+            .long("stats-interval")
+            .help("Seconds between status lines, 10 by default, 0 turns them off")
+            .takes_value(true))
+        .arg(Arg::with_name("metrics_addr")
+            .long("metrics-addr")
+            .help("Serve Prometheus metrics at http://<addr>/metrics, e.g. 127.0.0.1:9100")
+            .takes_value(true))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .help("Incremental mode: save positions of all threads to this file after every save of hits")
+            .takes_value(true))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .help("Continue the search from the --checkpoint file, results are appended to csv and jsonl files")
+            .requires("checkpoint"))
+        .arg(Arg::with_name("fallback_file")
+            .long("fallback-file")
+            .help("JSON Lines file for hits the results storage can't take, unsaved_hits.jsonl by default")
+            .takes_value(true))
+        .arg(Arg::with_name("queue_size")
+            .long("queue-size")
+            .help("Hits waiting for the writer thread before workers block, 10000 by default")
+            .takes_value(true))
+        .arg(Arg::with_name("flush_interval")
+            .long("flush-interval")
+            .help("Seconds between saves of buffered hits, 5 by default")
+            .takes_value(true))
+        .arg(Arg::with_name("encrypt_passphrase")
+            .long("encrypt-passphrase")
+            .help("Encrypt secrets and mnemonics in results with the passphrase from VANITY_PASSPHRASE env variable"))
+        .arg(Arg::with_name("recipient")
+            .long("recipient")
+            .help("Encrypt secrets and mnemonics in results to this X25519 or ed25519 public key, only its owner can decrypt them")
+            .takes_value(true)
+            .conflicts_with("encrypt_passphrase"))
+        .arg(Arg::with_name("seal_to")
+            .long("seal-to")
+            .help("Write-only mode: seal all key material of every found address to this X25519 or ed25519 public key")
+            .takes_value(true)
+            .conflicts_with_all(&["encrypt_passphrase", "recipient"]))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .help("Scoring mode: save only addresses which get into the top N scores seen so far")
+            .takes_value(true))
+        .arg(Arg::with_name("max_hits")
+            .long("max-hits")
+            .help("Stop after this amount of found addresses")
+            .takes_value(true))
+        .arg(Arg::with_name("max_time")
+            .long("max-time")
+            .help("Stop after this amount of seconds")
+            .takes_value(true))
+        .arg(Arg::with_name("max_attempts")
+            .long("max-attempts")
+            .help("Stop after this amount of generated addresses")
+            .takes_value(true))
+        .arg(Arg::with_name("first_per_pattern")
+            .long("first-per-pattern")
+            .help("Save only the first match of every pattern, stop when all patterns are found, \
+                   not allowed in scoring mode"))
+        .subcommand(SubCommand::with_name("estimate")
+            .about("Estimate match probability and time to find a match for the patterns and rules on this host")
+            .args(&settings::search_args())
+            .arg(Arg::with_name("duration")
+                .short("d")
+                .long("duration")
+                .help("Seconds to measure keys/sec for, 10 by default")
+                .takes_value(true))
+            .arg(Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .help("Random hashes to sample regex/glob patterns and beauty rules on, 1000000 by default")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("decrypt")
+            .about("Decrypt sealed values, whole csv rows or json lines; reads lines from stdin if no values given")
+            .arg(Arg::with_name("identity")
+                .short("i")
+                .long("identity")
+                .help("Path to file with hex X25519 private key, passphrase from VANITY_PASSPHRASE is used if not set")
+                .takes_value(true))
+            .arg(Arg::with_name("values")
+                .help("Sealed values or records")
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("combine")
+            .about("Combine the customer secret with a tweak found in split-key mode into the final key")
+            .arg(Arg::with_name("secret_file")
+                .short("s")
+                .long("secret-file")
+                .help("Path to file with hex customer secret: 32 bytes ed25519 secret or 64 bytes expanded key")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("tweak")
+                .long("tweak")
+                .help("Tweak from the secret field of the found record, tweak:<hex>")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("address")
+                .short("a")
+                .long("address")
+                .help("Found address, checked against the combined public key")
+                .takes_value(true))
+            .arg(Arg::with_name("contract_path")
+                .short("c")
+                .long("contract")
+                .help("Path to tvc file the address was generated for")
+                .takes_value(true))
+            .arg(Arg::with_name("workchain")
+                .short("w")
+                .long("workchain")
+                .help("Workchain id of the address")
+                .takes_value(true)
+                .allow_hyphen_values(true)))
+        .subcommand(SubCommand::with_name("keygen")
+            .about("Generate an X25519 identity for --recipient and --seal-to")
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Path to new file for the private key, identity.key by default")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("open")
+            .about("Open records sealed with --seal-to, prints key material of each one as json; reads lines from stdin if no values given")
+            .arg(Arg::with_name("identity")
+                .short("i")
+                .long("identity")
+                .help("Path to file with the private key: x25519:<hex>, ed25519:<hex seed> or bare X25519 hex")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("values")
+                .help("Sealed values or records")
+                .multiple(true)))
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("estimate") {
+        let settings = Settings::from_matches(matches)?;
+        let duration = matches.value_of("duration").unwrap_or("10")
+            .parse::<u64>()
+            .map_err(|e| format!("unable to parse \"duration\" arg as u64: {}", e))?;
+        let samples = matches.value_of("samples").unwrap_or("1000000")
+            .parse::<u64>()
+            .map_err(|e| format!("unable to parse \"samples\" arg as u64: {}", e))?;
+        settings.print();
+        return estimate::run(&settings, Duration::from_secs(duration), samples);
+    }
 
-    let with_mnemonic = matches.value_of("mnemonic").unwrap_or("false")
-        .parse::<bool>()
-        .map_err(|e| format!("unable to parse \"mnemonic\" arg as bool: {}", e))?;
-    let threads_amount = matches.value_of("threads_amount").unwrap_or("1")
-        .parse::<usize>()
-        .map_err(|e| format!("unable to parse \"threads_amount\" arg as usize: {}", e))?;
+    if let Some(matches) = matches.subcommand_matches("decrypt") {
+        return decrypt(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("combine") {
+        return combine(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("keygen") {
+        return keygen(matches.value_of("output").unwrap_or("identity.key"));
+    }
+    if let Some(matches) = matches.subcommand_matches("open") {
+        return open(matches);
+    }
 
-    let contract_path = matches.value_of("contract_path").unwrap_or("SetcodeMultisigWallet.tvc");
+    let settings = Settings::from_matches(&matches)?;
     let file_backend_path = matches.value_of("file_backend").unwrap_or("addresses.csv");
-    let file_backend = FileBackend::from_path(file_backend_path);
-    let connector = Arc::new(Mutex::new(file_backend.get_connector()));
+    let leaderboard = match matches.value_of("top") {
+        Some(top) => Some(Arc::new(Leaderboard::new(top.parse::<usize>()
+            .map_err(|e| format!("unable to parse \"top\" arg as usize: {}", e))?))),
+        None => None,
+    };
+    if leaderboard.is_some() && settings.min_score.is_none() {
+        return Err("\"top\" arg works only in scoring mode, set \"min_score\" too".to_string());
+    }
+    if matches.is_present("first_per_pattern") && settings.min_score.is_some() {
+        return Err("\"first_per_pattern\" arg doesn't work in scoring mode, drop \"min_score\" and \"top\"".to_string());
+    }
+    let conditions = StopConditions {
+        max_hits: parse_optional(&matches, "max_hits")?,
+        max_time: parse_optional(&matches, "max_time")?.map(Duration::from_secs),
+        max_attempts: parse_optional(&matches, "max_attempts")?,
+        first_per_pattern: matches.is_present("first_per_pattern"),
+    };
+    let pattern_names: Vec<String> = settings.patterns.all().iter().map(|p| p.describe()).collect();
+    let stop = Arc::new(StopState::new(conditions, pattern_names.iter().cloned().collect()));
+    let progress = progress(&matches, &settings)?;
+    let file_backend = match matches.value_of("format") {
+        Some(format) => FileBackend::from_path(file_backend_path).with_format(format)?,
+        None => FileBackend::from_path(file_backend_path),
+    }.with_append(matches.is_present("resume"));
+    let connector: Box<dyn Connector> = match matches.value_of("seal_to") {
+        Some(recipient) => Box::new(SealedConnector::new(file_backend.get_connector()?, recipient)?),
+        None => file_backend.get_connector()?,
+    };
+    let queue_size = parse_optional(&matches, "queue_size")?.unwrap_or(10000) as usize;
+    let flush_interval = Duration::from_secs(parse_optional(&matches, "flush_interval")?.unwrap_or(5));
+    let fallback_path = matches.value_of("fallback_file").unwrap_or("unsaved_hits.jsonl").to_string();
+    let seal_to = matches.value_of("seal_to").map(String::from);
+    let fallback: Fallback = Box::new(move || {
+        let connector: Box<dyn Connector> = Box::new(JsonlConnector::from_path(&fallback_path, true)?);
+        match &seal_to {
+            Some(recipient) => Ok(Box::new(SealedConnector::new(connector, recipient)?)),
+            None => Ok(connector),
+        }
+    });
+    let (writer, hit_sender) = ResultWriter::spawn(connector, queue_size, flush_interval, progress.clone(), fallback);
+    let signal_stop = stop.clone();
+    let unsaved = writer.unsaved();
+    let mut interrupted = false;
+    ctrlc::set_handler(move || {
+        if !interrupted {
+            interrupted = true;
+            signal_stop.stop("interrupted by signal, interrupt again to exit without waiting for the storage");
+            return;
+        }
+        println!("Interrupted again, exiting");
+        unsaved.dump();
+        process::exit(130);
+    }).map_err(|e| format!("unable to set signal handler: {}", e))?;
+    let stats_interval = parse_optional(&matches, "stats_interval")?.unwrap_or(10);
+    let metrics_addr = matches.value_of("metrics_addr");
+    let probability = if stats_interval > 0 || metrics_addr.is_some() {
+        estimate::match_probability(&settings, 100000)?
+    } else {
+        0.0
+    };
+    let stats = Arc::new(Stats::new(settings.threads_amount, pattern_names, probability));
+    if let Some(addr) = metrics_addr {
+        metrics::serve(addr, stats.clone(), writer.stats())?;
+    }
+    let sealer = if matches.is_present("encrypt_passphrase") {
+        Some(Arc::new(Sealer::from_passphrase(&passphrase()?)))
+    } else {
+        match matches.value_of("recipient") {
+            Some(recipient) => Some(Arc::new(Sealer::from_recipient(recipient)?)),
+            None => None,
+        }
+    };
 
     println!("Running Free TON Vanity address generator");
-    println!("Use mnemonic seed generator: {}", with_mnemonic);
-    println!("Threads amount: {}", threads_amount);
-    println!("Contract path: {}", contract_path);
+    settings.print();
     println!("File connector path: {}", file_backend_path);
+    if sealer.is_none() && !matches.is_present("seal_to") {
+        println!("WARNING: secrets are saved in plaintext, see --recipient and --encrypt-passphrase");
+    }
 
-    let handles: Vec<_> = (0..threads_amount)
-        .map(|_| {
-            let conn = connector.clone();
-            let contract_path = contract_path.to_string();
+    let reporter = if stats_interval > 0 {
+        Some(Reporter::spawn(stats.clone(), Duration::from_secs(stats_interval)))
+    } else {
+        None
+    };
+    let handles: Vec<_> = (0..settings.threads_amount)
+        .map(|thread_index| {
+            let hit_sender = hit_sender.clone();
+            let stats = stats.clone();
+            let progress = progress.clone();
+            let mut generator_settings = settings.generator_settings();
+            generator_settings.incremental_start = progress.as_ref().map(|p| p.start(thread_index));
+            generator_settings.seed = settings.seed.map(|seed| (seed, thread_index as u64));
+            let checker = settings.checker();
+            let leaderboard = leaderboard.clone();
+            let stop = stop.clone();
+            let sealer = sealer.clone();
             thread::spawn(move || {
                 println!("Started!");
-                run(conn, contract_path, with_mnemonic);
+                run(thread_index, hit_sender, checker, leaderboard, stop, stats, progress, sealer, generator_settings);
                 println!("Finished!");
             })
         })
         .collect();
+    drop(hit_sender);
 
 
     for handle in handles {
         handle.join().unwrap()
     }
+    if let Some(reporter) = reporter {
+        reporter.finish();
+    }
+    writer.finish();
+    stats.print_summary();
+    println!("Attempts: {}, hits: {}", stop.attempts(), stop.hits());
 
 
     Ok(())
 }
 
-fn run(file_backend_connector: Arc<Mutex<Box<dyn Connector>>>, path: String, with_mnemonic: bool) {
-    let bas = BeautyAddressCheck::new();
-    let mut address_generator = addr_gen::AccountGenerator::from_tvc_file(&path).unwrap();
-    let batch_size: u32 = 1000000;
-    // for _ in 0..10 {
+/// Checkpoint of an incremental search: loaded with `--resume`, otherwise new with random bases.
+fn progress(matches: &ArgMatches, settings: &Settings) -> Result<Option<Arc<Progress>>, String> {
+    let path = match matches.value_of("checkpoint") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if !settings.incremental {
+        return Err("checkpoints work only in incremental mode".to_string());
+    }
+    if ["seal_to", "recipient", "encrypt_passphrase"].iter().any(|arg| matches.is_present(arg)) {
+        return Err("checkpoint holds base secrets in plaintext, it can't be used with encrypted results".to_string());
+    }
+    let contract_hash = settings.generator_settings().generator()?.contract_hash;
+    let checkpoint = if matches.is_present("resume") {
+        Checkpoint::resume(path, &contract_hash, settings.workchain, settings.threads_amount)?
+    } else {
+        let mut rng = rand::thread_rng();
+        let bases: Vec<[u8; 32]> = (0..settings.threads_amount)
+            // same base as the thread would take without a checkpoint
+            .map(|thread| match settings.seed {
+                Some(seed) => IncrementalKeys::random_base(&mut AccountGenerator::seeded_rng(seed, thread as u64)),
+                None => IncrementalKeys::random_base(&mut rng),
+            })
+            .collect();
+        Checkpoint::create(path, contract_hash, settings.workchain, &bases)?
+    };
+    let progress = Progress::new(path, &checkpoint)?;
+    progress.save(&progress.positions())?;
+    println!("Checkpoint: {}{}", path, if matches.is_present("resume") { ", resumed" } else { "" });
+    Ok(Some(Arc::new(progress)))
+}
+
+fn parse_optional(matches: &ArgMatches, name: &str) -> Result<Option<u64>, String> {
+    match matches.value_of(name) {
+        Some(value) => value.parse::<u64>()
+            .map(Some)
+            .map_err(|e| format!("unable to parse \"{}\" arg as u64: {}", name, e)),
+        None => Ok(None),
+    }
+}
+
+fn passphrase() -> Result<String, String> {
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(passphrase),
+        _ => Err(format!("set the passphrase in {} env variable", PASSPHRASE_ENV)),
+    }
+}
+
+fn decrypt(matches: &ArgMatches) -> Result<(), String> {
+    let mut opener = match matches.value_of("identity") {
+        Some(path) => Opener::from_identity(&std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read identity file: {}", e))?)?,
+        None => Opener::from_passphrase(&passphrase()?),
+    };
+    for_each_line(matches, |line| {
+        println!("{}", opener.open_all(line)?);
+        Ok(())
+    })
+}
+
+fn open(matches: &ArgMatches) -> Result<(), String> {
+    let identity = std::fs::read_to_string(matches.value_of("identity").unwrap())
+        .map_err(|e| format!("unable to read identity file: {}", e))?;
+    let mut opener = Opener::from_identity(&identity)?;
+    for_each_line(matches, |line| {
+        for (start, end) in crypto::find_sealed(line) {
+            println!("{}", opener.open(&line[start..end])?);
+        }
+        Ok(())
+    })
+}
+
+/// Runs `f` for every value arg, or for every line of stdin if there are none.
+fn for_each_line<F: FnMut(&str) -> Result<(), String>>(matches: &ArgMatches, mut f: F) -> Result<(), String> {
+    match matches.values_of("values") {
+        Some(values) => {
+            for value in values {
+                f(value)?;
+            }
+        }
+        None => {
+            for line in io::stdin().lock().lines() {
+                f(&line.map_err(|e| format!("unable to read stdin: {}", e))?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn combine(matches: &ArgMatches) -> Result<(), String> {
+    let secret = std::fs::read_to_string(matches.value_of("secret_file").unwrap())
+        .map_err(|e| format!("unable to read secret file: {}", e))?;
+    let (expanded, public) = addr_gen::split_key::combine(&secret, matches.value_of("tweak").unwrap())?;
+    if let Some(address) = matches.value_of("address") {
+        let workchain = matches.value_of("workchain").unwrap_or("0")
+            .parse::<i8>()
+            .map_err(|e| format!("unable to parse \"workchain\" arg as i8: {}", e))?;
+        let contract_path = matches.value_of("contract_path").unwrap_or("SetcodeMultisigWallet.tvc");
+        let mut generator = addr_gen::AccountGenerator::from_tvc_file(contract_path, workchain)?;
+        let combined_address = generator.address_of(&public);
+        if combined_address != address {
+            return Err(format!("combined key gives address {}, not {}", combined_address, address));
+        }
+        println!("Address: {} (verified)", address);
+    }
+    println!("Public: {}", hex::encode(public.as_bytes()));
+    println!("Expanded secret: {}", hex::encode(&expanded[..]));
+    Ok(())
+}
+
+fn keygen(path: &str) -> Result<(), String> {
+    let (private, public) = crypto::generate_identity();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)
+        .map_err(|e| format!("unable to create identity file: {}", e))?;
+    writeln!(file, "{}", private).map_err(|e| format!("unable to write identity file: {}", e))?;
+    println!("Private key saved to {}, keep it offline", path);
+    println!("Public key: {}", public);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run(thread_index: usize, hit_sender: HitSender, bas: BeautyAddressCheck, leaderboard: Option<Arc<Leaderboard>>,
+       stop: Arc<StopState>, stats: Arc<Stats>, progress: Option<Arc<Progress>>, sealer: Option<Arc<Sealer>>,
+       generator_settings: GeneratorSettings) {
+    let mut address_generator = generator_settings.generator().unwrap();
+    let with_mnemonic = generator_settings.with_mnemonic;
+    let chunk_size: u64 = 1000;
     loop {
-        let batch_time_start = Instant::now();
-        for _ in 0..batch_size {
-            // let time_start = Instant::now();
-            let account;
+        let allowed = stop.reserve_attempts(chunk_size);
+        if allowed == 0 {
+            break;
+        }
+        let mut done = 0;
+        while done < allowed && !stop.is_stopped() {
+            done += 1;
+            let candidate;
             if with_mnemonic {
-                account = address_generator.generate_account_from_random_seed();
+                candidate = address_generator.candidate_from_random_seed();
             } else {
-                account = address_generator.generate_random_account();
+                candidate = address_generator.random_candidate();
             }
-            let id = account.account_id.clone();
-            let rule = bas.rule_beauty_address(&id);
-            if rule > 0 {
-                file_backend_connector.lock().unwrap().push(account, rule);
+            if let Some(hit) = bas.check_raw(&candidate.hash) {
+                let position = match (&progress, &candidate.secret) {
+                    (Some(progress), AccountSecret::Incremental { index, .. }) => {
+                        if progress.is_saved(thread_index, *index) {
+                            continue;
+                        }
+                        Some((thread_index, *index))
+                    }
+                    _ => None,
+                };
+                // before the leaderboard and stop conditions, so a dropped hit takes no slot
+                if let Err(e) = address_generator.verify_candidate(&candidate) {
+                    println!("Dropping hit: {}", e);
+                    stats.record_dropped();
+                    continue;
+                }
+                if let (Some(leaderboard), Some(score)) = (&leaderboard, hit.score) {
+                    if hit.rule == BeautyAddressCheck::SCORE_RULE && !leaderboard.offer(score) {
+                        continue;
+                    }
+                }
+                if !stop.accept_hit(&hit) {
+                    continue;
+                }
+                stats.record_hit(&hit);
+                let account = address_generator.account_from_candidate(candidate);
+                let mut record = Record::new(account, &hit, bas.friendly_format, sealer.as_deref());
+                record.position = position;
+                hit_sender.send(record);
             }
-            // let elapsed_time = time_start.elapsed();
-            // println!("{}", elapsed_time.as_micros());
-
         }
-        let batch_elapsed_time = batch_time_start.elapsed();
-        println!("TIME FOR {} addresses in one thread: {}", batch_size, batch_elapsed_time.as_secs());
+        stats.add_attempts(thread_index, done);
+        if let Some(progress) = &progress {
+            progress.advance(thread_index, done);
+        }
+        if done < allowed {
+            stop.release_attempts(allowed - done);
+            break;
+        }
     }
-    file_backend_connector.lock().unwrap().save();
 }
-
@@ -0,0 +1,213 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::stats::Stats;
+use crate::writer::WriterStats;
+
+const PREFIX: &str = "address_gen";
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Read and write timeout of a connection.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prometheus text format endpoint at `GET /metrics`, every connection is handled on its own
+/// short-lived thread, so a client which never sends its request doesn't block scrapes. Keys/sec
+/// are measured over a fixed window by another thread, so scrapes don't affect each other.
+pub fn serve(addr: &str, stats: Arc<Stats>, writer: Arc<WriterStats>) -> Result<(), String> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format!("unable to listen for metrics on {}: {}", addr, e))?;
+    println!("Metrics are served on http://{}/metrics", addr);
+    let rates = Arc::new(Mutex::new(vec![0.0; stats.thread_attempts().len()]));
+    let sampler_stats = stats.clone();
+    let sampler_rates = rates.clone();
+    thread::spawn(move || {
+        let mut last = (Instant::now(), sampler_stats.thread_attempts());
+        loop {
+            thread::sleep(RATE_WINDOW);
+            let attempts = sampler_stats.thread_attempts();
+            let elapsed = last.0.elapsed().as_secs_f64();
+            *sampler_rates.lock().unwrap() = attempts.iter().zip(&last.1)
+                .map(|(count, previous)| (count - previous) as f64 / elapsed)
+                .collect();
+            last = (Instant::now(), attempts);
+        }
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("unable to accept metrics connection: {}", e);
+                    continue;
+                }
+            };
+            let (stats, writer, rates) = (stats.clone(), writer.clone(), rates.clone());
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &stats, &writer, &rates) {
+                    println!("unable to send metrics: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: TcpStream, stats: &Stats, writer: &WriterStats, rates: &Mutex<Vec<f64>>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    match request_path(&stream) {
+        Some(path) if path == "/metrics" => {
+            let rates = rates.lock().unwrap().clone();
+            respond(stream, "200 OK", &render(stats, writer, &rates))
+        }
+        Some(_) => respond(stream, "404 Not Found", "not found, see /metrics\n"),
+        None => Ok(()),
+    }
+}
+
+fn request_path(stream: &TcpStream) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => Some(path.split('?').next().unwrap_or(path).to_string()),
+        _ => None,
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, body.len(), body)?;
+    stream.flush()
+}
+
+/// Label value with `\`, `"` and new lines escaped.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {}_{} {}", PREFIX, name, help).unwrap();
+    writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind).unwrap();
+}
+
+fn render(stats: &Stats, writer: &WriterStats, rates: &[f64]) -> String {
+    let mut out = String::new();
+    let attempts = stats.thread_attempts();
+
+    header(&mut out, "thread_attempts_total", "counter", "Generated addresses per thread.");
+    for (thread, count) in attempts.iter().enumerate() {
+        writeln!(out, "{}_thread_attempts_total{{thread=\"{}\"}} {}", PREFIX, thread, count).unwrap();
+    }
+    header(&mut out, "thread_keys_per_second", "gauge", "Keys/sec per thread over the last 10 seconds.");
+    for (thread, rate) in rates.iter().enumerate() {
+        writeln!(out, "{}_thread_keys_per_second{{thread=\"{}\"}} {:.1}", PREFIX, thread, rate).unwrap();
+    }
+    header(&mut out, "attempts_total", "counter", "Generated addresses of all threads.");
+    writeln!(out, "{}_attempts_total {}", PREFIX, attempts.iter().sum::<u64>()).unwrap();
+
+    header(&mut out, "hits_total", "counter", "Found addresses per rule.");
+    for (rule, hits) in stats.rule_hits() {
+        writeln!(out, "{}_hits_total{{rule=\"{}\"}} {}", PREFIX, rule, hits).unwrap();
+    }
+    header(&mut out, "pattern_hits_total", "counter", "Found addresses per pattern.");
+    for (pattern, hits) in stats.pattern_hits() {
+        writeln!(out, "{}_pattern_hits_total{{pattern=\"{}\"}} {}", PREFIX, label(&pattern), hits).unwrap();
+    }
+    header(&mut out, "match_chance", "gauge", "Chance to have found at least one match so far.");
+    writeln!(out, "{}_match_chance {}", PREFIX, stats.match_chance()).unwrap();
+
+    header(&mut out, "queue_depth", "gauge", "Hits waiting for the writer thread.");
+    writeln!(out, "{}_queue_depth {}", PREFIX, writer.queued()).unwrap();
+    header(&mut out, "written_total", "counter", "Hits pushed to the connector.");
+    writeln!(out, "{}_written_total {}", PREFIX, writer.written.load(Ordering::Relaxed)).unwrap();
+    header(&mut out, "flush_seconds", "summary", "Time spent saving buffered hits.");
+    writeln!(out, "{}_flush_seconds_sum {}", PREFIX, writer.flush_nanos.load(Ordering::Relaxed) as f64 / 1e9).unwrap();
+    writeln!(out, "{}_flush_seconds_count {}", PREFIX, writer.flushes.load(Ordering::Relaxed)).unwrap();
+    header(&mut out, "blocked_seconds", "summary", "Time workers waited on a full queue.");
+    writeln!(out, "{}_blocked_seconds_sum {}", PREFIX, writer.blocked_nanos.load(Ordering::Relaxed) as f64 / 1e9).unwrap();
+    writeln!(out, "{}_blocked_seconds_count {}", PREFIX, writer.blocked_sends.load(Ordering::Relaxed)).unwrap();
+
+    header(&mut out, "errors_total", "counter", "Failed saves, hits dropped by verification and hits lost by the writer.");
+    writeln!(out, "{}_errors_total{{kind=\"save\"}} {}", PREFIX, writer.save_errors.load(Ordering::Relaxed)).unwrap();
+    writeln!(out, "{}_errors_total{{kind=\"dropped\"}} {}", PREFIX, stats.dropped()).unwrap();
+    writeln!(out, "{}_errors_total{{kind=\"lost\"}} {}", PREFIX, writer.lost.load(Ordering::Relaxed)).unwrap();
+
+    header(&mut out, "uptime_seconds", "gauge", "Seconds since the search started.");
+    writeln!(out, "{}_uptime_seconds {:.3}", PREFIX, stats.elapsed().as_secs_f64()).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use crate::addr_checker::BeautyMatch;
+    use super::*;
+
+    fn metric(out: &str, name: &str) -> String {
+        let line = out.lines().find(|line| line.starts_with(&format!("{} ", name)))
+            .unwrap_or_else(|| panic!("no {} in\n{}", name, out));
+        line[name.len() + 1..].to_string()
+    }
+
+    #[test]
+    fn render_text_format() {
+        let stats = Stats::new(2, vec!["prefix:dead".to_string(), "regex:\"a\\b".to_string()], 0.0);
+        stats.add_attempts(0, 100);
+        stats.add_attempts(1, 50);
+        stats.record_hit(&BeautyMatch { rule: 10, pattern: "prefix:dead".to_string(), score: None });
+        stats.record_hit(&BeautyMatch { rule: 10, pattern: "prefix:dead".to_string(), score: None });
+        stats.record_dropped();
+        let writer = WriterStats::default();
+        writer.sent.store(5, Ordering::Relaxed);
+        writer.written.store(2, Ordering::Relaxed);
+        writer.flushes.store(3, Ordering::Relaxed);
+        writer.flush_nanos.store(1_500_000_000, Ordering::Relaxed);
+        writer.save_errors.store(4, Ordering::Relaxed);
+
+        let out = render(&stats, &writer, &[12.34, 0.0]);
+        assert_eq!(metric(&out, "address_gen_thread_attempts_total{thread=\"1\"}"), "50");
+        assert_eq!(metric(&out, "address_gen_thread_keys_per_second{thread=\"0\"}"), "12.3");
+        assert_eq!(metric(&out, "address_gen_attempts_total"), "150");
+        assert_eq!(metric(&out, "address_gen_hits_total{rule=\"10\"}"), "2");
+        assert_eq!(metric(&out, "address_gen_pattern_hits_total{pattern=\"prefix:dead\"}"), "2");
+        assert_eq!(metric(&out, "address_gen_pattern_hits_total{pattern=\"regex:\\\"a\\\\b\"}"), "0");
+        assert_eq!(metric(&out, "address_gen_match_chance"), "0");
+        assert_eq!(metric(&out, "address_gen_queue_depth"), "3");
+        assert_eq!(metric(&out, "address_gen_flush_seconds_sum"), "1.5");
+        assert_eq!(metric(&out, "address_gen_flush_seconds_count"), "3");
+        assert_eq!(metric(&out, "address_gen_errors_total{kind=\"save\"}"), "4");
+        assert_eq!(metric(&out, "address_gen_errors_total{kind=\"dropped\"}"), "1");
+        assert!(out.contains("# TYPE address_gen_flush_seconds summary\n"));
+        // every sample has HELP and TYPE lines and a number as its value
+        for line in out.lines().filter(|line| !line.starts_with('#')) {
+            let mut parts = line.rsplitn(2, ' ');
+            let (value, name) = (parts.next().unwrap(), parts.next().unwrap());
+            let family = name.split('{').next().unwrap().trim_end_matches("_sum").trim_end_matches("_count");
+            assert!(out.contains(&format!("# HELP {} ", family)), "{}", line);
+            assert!(out.contains(&format!("# TYPE {} ", family)), "{}", line);
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn idle_connection_does_not_block_scrapes() {
+        let addr = "127.0.0.1:0";
+        let listener = TcpListener::bind(addr).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        serve(&addr, Arc::new(Stats::new(1, vec![], 0.0)), Arc::new(WriterStats::default())).unwrap();
+
+        let _idle = TcpStream::connect(&addr).unwrap();
+        let mut scrape = TcpStream::connect(&addr).unwrap();
+        scrape.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        scrape.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("address_gen_attempts_total 0\n"));
+    }
+}
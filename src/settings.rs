@@ -0,0 +1,332 @@
+use clap::{Arg, ArgMatches};
+use crate::addr_checker::BeautyAddressCheck;
+use crate::addr_checker::config::RulesConfig;
+use crate::addr_checker::patterns::{PatternSet, PatternKind, AddressFormat};
+use crate::addr_gen::AccountGenerator;
+use crate::addr_gen::split_key::SplitKey;
+use crate::addr_gen::user_friendly::FriendlyFormat;
+
+/// Args describing what is searched and how keys are generated, shared by the generator
+/// and the `estimate` subcommand.
+pub fn search_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("mnemonic")
+            .short("m")
+            .long("mnemonic")
+            .help("Set true if you need to addresses with random mnemonic seed(much slower if set true)")
+            .takes_value(true),
+        Arg::with_name("contract_path")
+            .short("c")
+            .long("contract")
+            .help("Path to tvc file with contract for which addresses will be generated")
+            .takes_value(true),
+        Arg::with_name("threads_amount")
+            .short("t")
+            .long("threads")
+            .help("Amount of threads")
+            .takes_value(true),
+        Arg::with_name("patterns")
+            .short("p")
+            .long("patterns")
+            .help("Path to file with prefix/suffix/contains patterns to search, one per line")
+            .takes_value(true),
+        Arg::with_name("regex")
+            .short("r")
+            .long("regex")
+            .help("Regex over hex address to search, can be repeated")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("workchain")
+            .short("w")
+            .long("workchain")
+            .help("Workchain id of generated addresses: 0 for basechain, -1 for masterchain")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+        Arg::with_name("non_bounceable")
+            .long("non-bounceable")
+            .help("Match base64/base64url patterns against non-bounceable user-friendly address"),
+        Arg::with_name("testnet")
+            .long("testnet")
+            .help("Match base64/base64url patterns against testnet user-friendly address"),
+        Arg::with_name("rules")
+            .long("rules")
+            .help("Path to TOML or JSON file with beauty rules and their thresholds, built-in rules if not set")
+            .takes_value(true),
+        Arg::with_name("no_builtin_rules")
+            .long("no-builtin-rules")
+            .help("Check only patterns from --patterns file, skip beauty rules"),
+        Arg::with_name("split_key")
+            .long("split-key")
+            .help("Split-key mode: hex ed25519 public key of the customer, only tweaks to it are searched and saved")
+            .takes_value(true),
+        Arg::with_name("incremental")
+            .long("incremental")
+            .help("Fast mode: step the public key from one random key by point addition, keys are saved as 64 bytes expanded keys"),
+        Arg::with_name("seed")
+            .long("seed")
+            .help("Tests and debugging only: derive every key from this u64 seed, keys are NOT secret")
+            .takes_value(true),
+        Arg::with_name("min_score")
+            .long("min-score")
+            .help("Scoring mode: score every address by all beauty criteria and save ones with at least this score")
+            .takes_value(true),
+    ]
+}
+
+/// Everything a worker thread needs to build its own `AccountGenerator`.
+#[derive(Clone)]
+pub struct GeneratorSettings {
+    pub contract_path: String,
+    pub workchain: i8,
+    pub with_mnemonic: bool,
+    pub split_key: Option<SplitKey>,
+    pub incremental: bool,
+    /// Base and first index of the incremental stream, random if not set.
+    pub incremental_start: Option<([u8; 32], u64)>,
+    /// Seed and stream of the generator rng in deterministic mode.
+    pub seed: Option<(u64, u64)>,
+}
+
+impl GeneratorSettings {
+    pub fn generator(&self) -> Result<AccountGenerator, String> {
+        let generator = AccountGenerator::from_tvc_file(&self.contract_path, self.workchain)?;
+        let generator = match self.seed {
+            Some((seed, stream)) => generator.with_seed(seed, stream),
+            None => generator,
+        };
+        let generator = match self.split_key {
+            Some(split_key) => generator.with_split_key(split_key),
+            None => generator,
+        };
+        Ok(match (self.incremental, self.incremental_start) {
+            (true, Some((base, index))) => generator.with_incremental_from(base, index),
+            (true, None) => generator.with_incremental(),
+            _ => generator,
+        })
+    }
+}
+
+pub struct Settings {
+    pub with_mnemonic: bool,
+    pub threads_amount: usize,
+    pub contract_path: String,
+    pub workchain: i8,
+    pub patterns: PatternSet,
+    pub rules: RulesConfig,
+    pub friendly_format: FriendlyFormat,
+    pub min_score: Option<u32>,
+    pub split_key: Option<SplitKey>,
+    pub incremental: bool,
+    pub seed: Option<u64>,
+}
+
+impl Settings {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let with_mnemonic = matches.value_of("mnemonic").unwrap_or("false")
+            .parse::<bool>()
+            .map_err(|e| format!("unable to parse \"mnemonic\" arg as bool: {}", e))?;
+        let threads_amount = matches.value_of("threads_amount").unwrap_or("1")
+            .parse::<usize>()
+            .map_err(|e| format!("unable to parse \"threads_amount\" arg as usize: {}", e))?;
+        let workchain = matches.value_of("workchain").unwrap_or("0")
+            .parse::<i8>()
+            .map_err(|e| format!("unable to parse \"workchain\" arg as i8: {}", e))?;
+        let contract_path = matches.value_of("contract_path").unwrap_or("SetcodeMultisigWallet.tvc");
+
+        let mut patterns = match matches.value_of("patterns") {
+            Some(path) => PatternSet::from_file(path)?,
+            None => PatternSet::new(),
+        };
+        if let Some(regexes) = matches.values_of("regex") {
+            let mut all = patterns.all();
+            for regex in regexes {
+                all.push(PatternSet::make_pattern(PatternKind::Regex, AddressFormat::Hex, regex, PatternSet::DEFAULT_RULE)?);
+            }
+            patterns = PatternSet::from_patterns(all)?;
+        }
+        let friendly_format = FriendlyFormat {
+            bounceable: !matches.is_present("non_bounceable"),
+            testnet: matches.is_present("testnet"),
+            ..FriendlyFormat::default()
+        };
+        let rules = if matches.is_present("no_builtin_rules") {
+            RulesConfig::empty()
+        } else {
+            match matches.value_of("rules") {
+                Some(path) => RulesConfig::from_file(path)?,
+                None => RulesConfig::default(),
+            }
+        };
+        // fail on a broken rules file before starting threads
+        rules.compile()?;
+        let min_score = match matches.value_of("min_score") {
+            Some(score) => Some(score.parse::<u32>()
+                .map_err(|e| format!("unable to parse \"min_score\" arg as u32: {}", e))?),
+            None => None,
+        };
+        let split_key = match matches.value_of("split_key") {
+            Some(public) => Some(SplitKey::from_public(public)?),
+            None => None,
+        };
+        if split_key.is_some() && with_mnemonic {
+            return Err("split-key mode can't generate mnemonics, set \"mnemonic\" to false".to_string());
+        }
+        let incremental = matches.is_present("incremental");
+        if incremental && (with_mnemonic || split_key.is_some()) {
+            return Err("incremental mode can't be combined with mnemonic or split-key modes".to_string());
+        }
+        let seed = match matches.value_of("seed") {
+            Some(seed) => Some(seed.parse::<u64>()
+                .map_err(|e| format!("unable to parse \"seed\" arg as u64: {}", e))?),
+            None => None,
+        };
+        if rules.rules.iter().all(|r| !r.enabled) && patterns.is_empty() && min_score.is_none() {
+            return Err("nothing to search: all beauty rules are disabled and no patterns provided".to_string());
+        }
+
+        Ok(Self {
+            with_mnemonic,
+            threads_amount,
+            contract_path: contract_path.to_string(),
+            workchain,
+            patterns,
+            rules,
+            friendly_format,
+            min_score,
+            split_key,
+            incremental,
+            seed,
+        })
+    }
+
+    pub fn generator_settings(&self) -> GeneratorSettings {
+        GeneratorSettings {
+            contract_path: self.contract_path.clone(),
+            workchain: self.workchain,
+            with_mnemonic: self.with_mnemonic,
+            split_key: self.split_key,
+            incremental: self.incremental,
+            incremental_start: None,
+            seed: self.seed.map(|seed| (seed, 0)),
+        }
+    }
+
+    pub fn checker(&self) -> BeautyAddressCheck {
+        self.checker_with(&self.rules, self.patterns.clone())
+    }
+
+    pub fn checker_with(&self, rules: &RulesConfig, patterns: PatternSet) -> BeautyAddressCheck {
+        // rules are validated in from_matches
+        let checker = BeautyAddressCheck::from_config(rules, patterns).unwrap()
+            .with_friendly_format(self.friendly_format)
+            .with_workchain(self.workchain);
+        match self.min_score {
+            Some(min_score) => checker.with_scoring(min_score),
+            None => checker,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("Use mnemonic seed generator: {}", self.with_mnemonic);
+        println!("Threads amount: {}", self.threads_amount);
+        println!("Contract path: {}", self.contract_path);
+        println!("Workchain: {}", self.workchain);
+        println!("Patterns amount: {}", self.patterns.len());
+        println!("Beauty rules: {}", self.rules.rules.iter()
+            .filter(|r| r.enabled)
+            .map(|r| r.id.to_string())
+            .collect::<Vec<String>>()
+            .join(","));
+        if let Some(min_score) = self.min_score {
+            println!("Scoring mode, min score: {}", min_score);
+        }
+        if self.split_key.is_some() {
+            println!("Split-key mode: saving tweaks to the customer key");
+        }
+        if self.incremental {
+            println!("Incremental mode: saving 64 bytes expanded keys");
+        }
+        if let Some(seed) = self.seed {
+            println!("{}", "!".repeat(80));
+            println!("WARNING: deterministic mode, every key is derived from seed {}.", seed);
+            println!("WARNING: anyone who knows the seed can recreate all keys, never use them for real funds.");
+            println!("{}", "!".repeat(80));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr_gen::split_key::combine;
+
+    fn seeded(stream: u64, with_mnemonic: bool, incremental: bool) -> GeneratorSettings {
+        GeneratorSettings {
+            contract_path: format!("{}/SetcodeMultisigWallet.tvc", env!("CARGO_MANIFEST_DIR")),
+            workchain: 0,
+            with_mnemonic,
+            split_key: None,
+            incremental,
+            incremental_start: None,
+            seed: Some((42, stream)),
+        }
+    }
+
+    #[test]
+    fn seeded_random_keypair() {
+        let mut generator = seeded(0, false, false).generator().unwrap();
+        let candidate = generator.random_candidate();
+        let account = generator.account_from_candidate(candidate);
+        assert_eq!(account.address(), "0:e3b38821cc970fa4a1c8c2b6d38602f092d9df6d2f76d5e7d8f21c9fbf0105bd");
+        assert_eq!(account.public_as_string(), "78eda21ba04a15e2000fe8810fe3e56741d23bb9ae44aa9d5bb21b76675ff34b");
+        assert_eq!(account.secret_as_string(), "7848b5d711bc9883996317a3f9c90269d56771005d540a19184939c9e8d0db2a");
+    }
+
+    #[test]
+    fn seeded_mnemonic() {
+        let mut generator = seeded(0, true, false).generator().unwrap();
+        let candidate = generator.candidate_from_random_seed();
+        let account = generator.account_from_candidate(candidate);
+        assert_eq!(account.seed, "joke east into casino sister dove grain glass physical soft dog square");
+        assert_eq!(account.address(), "0:68a0b0a1d888c1b3f8dc5928df8a74e060bf6d53a3f06d6a484a69440f6ddbdc");
+        assert_eq!(account.public_as_string(), "bfd0e866b3d2954a36880aadd2b0ad9f6db890ae50d9dc7b706efa3945877ee8");
+    }
+
+    #[test]
+    fn seeded_incremental() {
+        let mut generator = seeded(0, false, true).generator().unwrap();
+        let candidate = generator.random_candidate();
+        generator.verify_candidate(&candidate).unwrap();
+        let account = generator.account_from_candidate(candidate);
+        assert_eq!(account.address(), "0:ee3f7bd17328ceac45c20db3c22350e399cfd9c1f12a83d7c8c96c1a4834d2d8");
+        assert_eq!(account.public_as_string(), "f7f076da568a8cd0154a1fb856857d36afcec4247e3059de5df4e313557574ef");
+        assert_eq!(account.secret_as_string(), "7848b5d711bc9883996317a3f9c90269d56771005d540a19184939c9e8d0db4a\
+                                                8d14dcf397a3518f6c05e702c21362b1d6f9a7479afcba1e7bc63bbc573f3d81");
+    }
+
+    #[test]
+    fn split_key_combined_address() {
+        let customer = ed25519_dalek::SecretKey::from_bytes(&[9; 32]).unwrap();
+        let customer_public = hex::encode(ed25519_dalek::PublicKey::from(&customer).as_bytes());
+        let mut settings = seeded(0, false, false);
+        settings.split_key = Some(SplitKey::from_public(&customer_public).unwrap());
+        let mut generator = settings.generator().unwrap();
+        let candidate = generator.random_candidate();
+        generator.verify_candidate(&candidate).unwrap();
+        let account = generator.account_from_candidate(candidate);
+        let (_, public) = combine(&hex::encode(customer.as_bytes()), &account.secret_as_string()).unwrap();
+        assert_eq!(public, account.public);
+        assert_eq!(generator.address_of(&public), account.address());
+    }
+
+    #[test]
+    fn seeded_streams_differ() {
+        let mut first = seeded(0, false, false).generator().unwrap();
+        let mut second = seeded(1, false, false).generator().unwrap();
+        let (a, b) = (first.random_candidate(), second.random_candidate());
+        assert_ne!(a.public, b.public);
+        assert_ne!(a.hash, b.hash);
+        assert_eq!(hex::encode(b.public), "f102e8f902918325afafffd4b8a73ed4049a775a82e33effab7107a6d34201fc");
+    }
+}
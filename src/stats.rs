@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::addr_checker::BeautyMatch;
+use crate::estimate::format_duration;
+
+/// Counters shared by worker threads, read by the reporter.
+pub struct Stats {
+    started: Instant,
+    threads: Vec<AtomicU64>,
+    rules: Vec<AtomicU64>,
+    patterns: HashMap<String, AtomicU64>,
+    dropped: AtomicU64,
+    probability: f64,
+}
+
+impl Stats {
+    /// `probability` is the chance of a single candidate to be a hit, see `estimate::match_probability`.
+    pub fn new(threads: usize, patterns: Vec<String>, probability: f64) -> Self {
+        Self {
+            started: Instant::now(),
+            threads: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            rules: (0..=u8::MAX).map(|_| AtomicU64::new(0)).collect(),
+            patterns: patterns.into_iter().map(|p| (p, AtomicU64::new(0))).collect(),
+            dropped: AtomicU64::new(0),
+            probability,
+        }
+    }
+
+    pub fn add_attempts(&self, thread: usize, attempts: u64) {
+        self.threads[thread].fetch_add(attempts, Ordering::Relaxed);
+    }
+
+    pub fn record_hit(&self, hit: &BeautyMatch) {
+        self.rules[hit.rule as usize].fetch_add(1, Ordering::Relaxed);
+        if let Some(counter) = self.patterns.get(&hit.pattern) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Hit which failed verification and was not saved.
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn thread_attempts(&self) -> Vec<u64> {
+        self.threads.iter().map(|t| t.load(Ordering::Relaxed)).collect()
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.thread_attempts().iter().sum()
+    }
+
+    /// Rules with at least one hit.
+    pub fn rule_hits(&self) -> Vec<(u8, u64)> {
+        self.rules.iter().enumerate()
+            .map(|(rule, hits)| (rule as u8, hits.load(Ordering::Relaxed)))
+            .filter(|(_, hits)| *hits > 0)
+            .collect()
+    }
+
+    pub fn pattern_hits(&self) -> Vec<(String, u64)> {
+        let mut hits: Vec<(String, u64)> = self.patterns.iter()
+            .map(|(pattern, hits)| (pattern.clone(), hits.load(Ordering::Relaxed)))
+            .collect();
+        hits.sort();
+        hits
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.rule_hits().iter().map(|(_, hits)| hits).sum()
+    }
+
+    /// Chance to have found at least one match after the attempts made so far.
+    pub fn match_chance(&self) -> f64 {
+        -(self.attempts() as f64 * (-self.probability).ln_1p()).exp_m1()
+    }
+
+    pub fn status_line(&self, rate: f64) -> String {
+        let rules: Vec<String> = self.rule_hits().iter()
+            .map(|(rule, hits)| format!("{}:{}", rule, hits))
+            .collect();
+        format!("[{}] {:.0} keys/s | attempts {} | hits {} ({}) | match chance so far {:.1}%",
+                format_duration(self.elapsed().as_secs_f64()), rate, self.attempts(), self.hits(),
+                if rules.is_empty() { "-".to_string() } else { rules.join(" ") },
+                self.match_chance() * 100.0)
+    }
+
+    pub fn print_summary(&self) {
+        let elapsed = self.elapsed().as_secs_f64();
+        for (thread, attempts) in self.thread_attempts().iter().enumerate() {
+            println!("Thread {}: {} attempts, {:.0} keys/s", thread, attempts, *attempts as f64 / elapsed);
+        }
+        for (rule, hits) in self.rule_hits() {
+            println!("Rule {}: {} hits", rule, hits);
+        }
+        for (pattern, hits) in self.pattern_hits() {
+            println!("Pattern {}: {} hits", pattern, hits);
+        }
+    }
+}
+
+/// Prints the status line every `interval`, in place on a terminal.
+pub struct Reporter {
+    handle: JoinHandle<()>,
+    done: Arc<AtomicBool>,
+}
+
+impl Reporter {
+    const TICK: Duration = Duration::from_millis(100);
+
+    pub fn spawn(stats: Arc<Stats>, interval: Duration) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let reporter_done = done.clone();
+        let handle = thread::spawn(move || {
+            let tty = atty::is(atty::Stream::Stdout);
+            let mut last = (Instant::now(), stats.attempts());
+            while !reporter_done.load(Ordering::Relaxed) {
+                thread::sleep(Self::TICK);
+                if last.0.elapsed() < interval {
+                    continue;
+                }
+                let attempts = stats.attempts();
+                let rate = (attempts - last.1) as f64 / last.0.elapsed().as_secs_f64();
+                last = (Instant::now(), attempts);
+                if tty {
+                    print!("\r{}  ", stats.status_line(rate));
+                    io::stdout().flush().ok();
+                } else {
+                    println!("{}", stats.status_line(rate));
+                }
+            }
+            if tty {
+                println!();
+            }
+        });
+        Self { handle, done }
+    }
+
+    pub fn finish(self) {
+        self.done.store(true, Ordering::Relaxed);
+        self.handle.join().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(rule: u8, pattern: &str) -> BeautyMatch {
+        BeautyMatch { rule, pattern: pattern.to_string(), score: None }
+    }
+
+    #[test]
+    fn hits_per_rule_and_pattern() {
+        let stats = Stats::new(2, vec!["suffix:beef".to_string(), "prefix:dead".to_string()], 0.0);
+        stats.record_hit(&hit(10, "prefix:dead"));
+        stats.record_hit(&hit(10, "prefix:dead"));
+        stats.record_hit(&hit(12, "suffix:beef"));
+        stats.record_hit(&hit(3, "prefix"));
+        stats.record_hit(&hit(255, "contains:0"));
+        assert_eq!(stats.rule_hits(), vec![(3, 1), (10, 2), (12, 1), (255, 1)]);
+        assert_eq!(stats.hits(), 5);
+        // only the configured patterns are counted, sorted by name
+        assert_eq!(stats.pattern_hits(), vec![("prefix:dead".to_string(), 2), ("suffix:beef".to_string(), 1)]);
+    }
+
+    #[test]
+    fn attempts_and_match_chance() {
+        let stats = Stats::new(2, vec![], 0.5);
+        assert_eq!(stats.match_chance(), 0.0);
+        stats.add_attempts(0, 1);
+        stats.add_attempts(1, 1);
+        stats.add_attempts(1, 1);
+        assert_eq!(stats.thread_attempts(), vec![1, 2]);
+        assert_eq!(stats.attempts(), 3);
+        assert!((stats.match_chance() - 0.875).abs() < 1e-12);
+    }
+
+    /// Status line without the elapsed time at its start.
+    fn status(stats: &Stats, rate: f64) -> String {
+        let line = stats.status_line(rate);
+        assert!(line.starts_with('['), "{}", line);
+        line[line.find("] ").unwrap() + 2..].to_string()
+    }
+
+    #[test]
+    fn status_line() {
+        let stats = Stats::new(1, vec![], 1e-3);
+        assert_eq!(status(&stats, 0.0), "0 keys/s | attempts 0 | hits 0 (-) | match chance so far 0.0%");
+        stats.add_attempts(0, 1000);
+        stats.record_hit(&hit(10, "prefix:dead"));
+        stats.record_hit(&hit(2, "keyword"));
+        stats.record_hit(&hit(10, "prefix:dead"));
+        assert_eq!(status(&stats, 1234.56), "1235 keys/s | attempts 1000 | hits 3 (2:1 10:2) | match chance so far 63.2%");
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::addr_checker::BeautyMatch;
+
+#[derive(Clone, Debug, Default)]
+pub struct StopConditions {
+    pub max_hits: Option<u64>,
+    pub max_time: Option<Duration>,
+    pub max_attempts: Option<u64>,
+    pub first_per_pattern: bool,
+}
+
+/// Shared by all worker threads: counts attempts and hits and raises the stop flag once any
+/// of the conditions fires.
+pub struct StopState {
+    conditions: StopConditions,
+    stopped: AtomicBool,
+    started: Instant,
+    attempts: AtomicU64,
+    hits: AtomicU64,
+    patterns: HashSet<String>,
+    found: Mutex<HashSet<String>>,
+}
+
+impl StopState {
+    pub fn new(conditions: StopConditions, patterns: HashSet<String>) -> Self {
+        Self {
+            conditions,
+            stopped: AtomicBool::new(false),
+            started: Instant::now(),
+            attempts: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            patterns,
+            found: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn stop(&self, reason: &str) {
+        if !self.stopped.swap(true, Ordering::SeqCst) {
+            println!("Stopping: {}", reason);
+        }
+    }
+
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// How many of the next `chunk` attempts the worker may make, 0 means it has to stop.
+    pub fn reserve_attempts(&self, chunk: u64) -> u64 {
+        if self.is_stopped() {
+            return 0;
+        }
+        if let Some(max_time) = self.conditions.max_time {
+            if self.started.elapsed() >= max_time {
+                self.stop(&format!("time limit of {}s reached", max_time.as_secs()));
+                return 0;
+            }
+        }
+        let before = self.attempts.fetch_add(chunk, Ordering::Relaxed);
+        match self.conditions.max_attempts {
+            Some(max_attempts) if before >= max_attempts => {
+                self.attempts.fetch_sub(chunk, Ordering::Relaxed);
+                self.stop(&format!("{} attempts made", max_attempts));
+                0
+            }
+            Some(max_attempts) if before + chunk > max_attempts => {
+                let allowed = max_attempts - before;
+                self.attempts.fetch_sub(chunk - allowed, Ordering::Relaxed);
+                allowed
+            }
+            _ => chunk,
+        }
+    }
+
+    /// Gives back reserved attempts the worker didn't make, so the total stays exact after a stop.
+    pub fn release_attempts(&self, unused: u64) {
+        self.attempts.fetch_sub(unused, Ordering::Relaxed);
+    }
+
+    /// Whether the hit has to be saved: with `first_per_pattern` repeated hits of a pattern are
+    /// dropped, and the search stops once every pattern is found.
+    pub fn accept_hit(&self, hit: &BeautyMatch) -> bool {
+        if !self.conditions.first_per_pattern {
+            return self.count_hit();
+        }
+        // the lock is held until the pattern is taken, so a hit over `max_hits` doesn't mark it found
+        let mut found = self.found.lock().unwrap();
+        if found.contains(&hit.pattern) || !self.count_hit() {
+            return false;
+        }
+        found.insert(hit.pattern.clone());
+        if !self.patterns.is_empty() && self.patterns.iter().all(|p| found.contains(p)) {
+            self.stop("every pattern is found");
+        }
+        true
+    }
+
+    /// Counts the hit against `max_hits`, a hit over the limit is not counted.
+    fn count_hit(&self) -> bool {
+        let hits = self.hits.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max_hits) = self.conditions.max_hits {
+            if hits > max_hits {
+                self.hits.fetch_sub(1, Ordering::Relaxed);
+                return false;
+            }
+            if hits == max_hits {
+                self.stop(&format!("{} hits found", max_hits));
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(pattern: &str) -> BeautyMatch {
+        BeautyMatch { rule: 0, pattern: pattern.to_string(), score: None }
+    }
+
+    fn state(max_hits: Option<u64>, first_per_pattern: bool) -> StopState {
+        let conditions = StopConditions { max_hits, first_per_pattern, ..Default::default() };
+        StopState::new(conditions, ["a", "b", "c"].iter().map(|p| p.to_string()).collect())
+    }
+
+    #[test]
+    fn max_hits() {
+        let state = state(Some(2), false);
+        assert!(state.accept_hit(&hit("a")));
+        assert!(!state.is_stopped());
+        assert!(state.accept_hit(&hit("a")));
+        assert!(state.is_stopped());
+        assert!(!state.accept_hit(&hit("b")));
+        assert_eq!(state.hits(), 2);
+    }
+
+    #[test]
+    fn first_per_pattern() {
+        let state = state(None, true);
+        assert!(state.accept_hit(&hit("a")));
+        assert!(!state.accept_hit(&hit("a")));
+        assert!(state.accept_hit(&hit("b")));
+        assert!(!state.is_stopped());
+        assert!(state.accept_hit(&hit("c")));
+        assert!(state.is_stopped());
+        assert_eq!(state.hits(), 3);
+    }
+
+    #[test]
+    fn rejected_hit_leaves_pattern_unfound() {
+        let state = state(Some(1), true);
+        assert!(state.accept_hit(&hit("a")));
+        assert!(!state.accept_hit(&hit("b")));
+        assert!(!state.found.lock().unwrap().contains("b"));
+        assert_eq!(state.hits(), 1);
+    }
+
+    #[test]
+    fn attempts_are_capped() {
+        let conditions = StopConditions { max_attempts: Some(250), ..Default::default() };
+        let state = StopState::new(conditions, HashSet::new());
+        assert_eq!(state.reserve_attempts(100), 100);
+        assert_eq!(state.reserve_attempts(100), 100);
+        assert_eq!(state.reserve_attempts(100), 50);
+        assert_eq!(state.reserve_attempts(100), 0);
+        assert!(state.is_stopped());
+        assert_eq!(state.attempts(), 250);
+    }
+}
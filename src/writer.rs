@@ -0,0 +1,296 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::file_backend::{Connector, Record};
+use crate::checkpoint::Progress;
+
+/// Backpressure of the result pipeline: how many hits went through and how long workers waited
+/// on a full queue.
+#[derive(Default)]
+pub struct WriterStats {
+    pub sent: AtomicU64,
+    pub written: AtomicU64,
+    pub blocked_sends: AtomicU64,
+    pub blocked_nanos: AtomicU64,
+    pub flushes: AtomicU64,
+    pub flush_nanos: AtomicU64,
+    /// Failed saves reported by the connector.
+    pub save_errors: AtomicU64,
+    /// Hits sent after the writer thread is gone.
+    pub lost: AtomicU64,
+}
+
+impl WriterStats {
+    /// Hits sent by workers and not yet pushed to the connector.
+    pub fn queued(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed).saturating_sub(self.written.load(Ordering::Relaxed))
+    }
+
+    pub fn print(&self) {
+        println!("Writer: {} hits written, {} flushes ({:.3}s), {} sends blocked on a full queue ({:.3}s)",
+                 self.written.load(Ordering::Relaxed),
+                 self.flushes.load(Ordering::Relaxed),
+                 self.flush_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+                 self.blocked_sends.load(Ordering::Relaxed),
+                 self.blocked_nanos.load(Ordering::Relaxed) as f64 / 1e9);
+    }
+}
+
+/// Worker side of the pipeline, one clone per thread.
+#[derive(Clone)]
+pub struct HitSender {
+    sender: SyncSender<Record>,
+    stats: Arc<WriterStats>,
+}
+
+impl HitSender {
+    /// Blocks only when the queue is full, which is counted as backpressure.
+    pub fn send(&self, record: Record) {
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        let record = match self.sender.try_send(record) {
+            Ok(()) => return,
+            Err(TrySendError::Full(record)) => record,
+            Err(TrySendError::Disconnected(_)) => {
+                println!("Writer thread is gone, hit is lost");
+                self.stats.lost.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let start = Instant::now();
+        if self.sender.send(record).is_err() {
+            println!("Writer thread is gone, hit is lost");
+            self.stats.lost.fetch_add(1, Ordering::Relaxed);
+        }
+        self.stats.blocked_sends.fetch_add(1, Ordering::Relaxed);
+        self.stats.blocked_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Opens the connector of the fallback file, which takes hits the main connector can't.
+pub type Fallback = Box<dyn Fn() -> Result<Box<dyn Connector>, String> + Send>;
+
+/// Fallback file, opened on first use.
+struct FallbackFile {
+    open: Fallback,
+    connector: Option<Box<dyn Connector>>,
+}
+
+impl FallbackFile {
+    /// Appends `records` and tells whether they are saved, records of a failed write stay in the
+    /// file connector for the next one.
+    fn save(&mut self, records: Vec<Record>, stats: &WriterStats) -> bool {
+        if self.connector.is_none() {
+            match (self.open)() {
+                Ok(connector) => self.connector = Some(connector),
+                Err(e) => {
+                    println!("unable to open the fallback file: {}, {} hits are lost", e, records.len());
+                    stats.lost.fetch_add(records.len() as u64, Ordering::Relaxed);
+                    return false;
+                }
+            }
+        }
+        let connector = self.connector.as_mut().unwrap();
+        let errors = connector.errors();
+        for record in records {
+            connector.push(record);
+        }
+        connector.save();
+        connector.errors() == errors
+    }
+}
+
+/// Hits the connector hasn't saved yet and the fallback file, shared with the signal handler.
+/// Locks are never held while the connector saves, so a hung storage doesn't block a dump.
+#[derive(Clone)]
+pub struct Unsaved {
+    /// copies of the records pushed since the last successful save
+    records: Arc<Mutex<Vec<Record>>>,
+    fallback: Arc<Mutex<FallbackFile>>,
+    stats: Arc<WriterStats>,
+}
+
+impl Unsaved {
+    fn records(&self) -> MutexGuard<'_, Vec<Record>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn save_to_fallback(&self, records: Vec<Record>) -> bool {
+        self.fallback.lock().unwrap_or_else(PoisonError::into_inner).save(records, &self.stats)
+    }
+
+    /// Appends the unsaved hits to the fallback file, for an exit without waiting for the
+    /// writer thread. Hits still in the queue are lost.
+    pub fn dump(&self) {
+        let records = std::mem::take(&mut *self.records());
+        let count = records.len();
+        if count > 0 {
+            if self.save_to_fallback(records) {
+                println!("{} unsaved hits are saved to the fallback file", count);
+            } else {
+                println!("The fallback file can't be written, {} hits may be lost", count);
+            }
+        }
+        let queued = self.stats.queued();
+        if queued > 0 {
+            println!("{} queued hits are lost", queued);
+        }
+    }
+}
+
+/// State of the writer thread.
+struct Writer {
+    connector: Box<dyn Connector>,
+    stats: Arc<WriterStats>,
+    progress: Option<Arc<Progress>>,
+    unsaved: Unsaved,
+    /// the last save failed
+    failing: bool,
+}
+
+impl Writer {
+    /// Hits kept for the connector while its saves fail, later ones go to the fallback file.
+    const MAX_UNSAVED: usize = 100_000;
+
+    fn push(&mut self, record: Record) {
+        if self.unsaved.records().len() >= Self::MAX_UNSAVED && !self.failing {
+            self.flush(None);
+        }
+        if self.connector.is_full() || self.unsaved.records().len() >= Self::MAX_UNSAVED {
+            // the connector is down and holds as much as it may, don't wait for it
+            self.unsaved.save_to_fallback(vec![record]);
+        } else {
+            self.unsaved.records().push(record.clone());
+            self.connector.push(record);
+        }
+        self.stats.written.fetch_add(1, Ordering::Relaxed);
+        self.stats.save_errors.store(self.connector.errors(), Ordering::Relaxed);
+    }
+
+    /// Saves hits, then the checkpoint at `positions` taken before the hits below them were
+    /// pushed. Returns false if the save failed, then `unsaved` keeps the records and the
+    /// checkpoint is left behind, so unsaved hits are searched again on resume.
+    fn flush(&mut self, positions: Option<Vec<u64>>) -> bool {
+        let errors = self.connector.errors();
+        let start = Instant::now();
+        self.connector.save();
+        self.stats.flushes.fetch_add(1, Ordering::Relaxed);
+        self.stats.flush_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.stats.save_errors.store(self.connector.errors(), Ordering::Relaxed);
+        self.failing = self.connector.errors() != errors;
+        if self.failing {
+            return false;
+        }
+        let progress = match &self.progress {
+            Some(progress) => progress,
+            None => {
+                self.unsaved.records().clear();
+                return true;
+            }
+        };
+        for record in self.unsaved.records().drain(..) {
+            if let Some((thread, index)) = record.position {
+                progress.mark_saved(thread, index);
+            }
+        }
+        if let Some(positions) = positions {
+            if let Err(e) = progress.save(&positions) {
+                println!("{}", e);
+            }
+        }
+        true
+    }
+
+    fn finish(mut self) {
+        let positions = self.progress.as_ref().map(|progress| progress.positions());
+        if self.flush(positions) {
+            return;
+        }
+        let unsaved = std::mem::take(&mut *self.unsaved.records());
+        let count = unsaved.len();
+        if count == 0 {
+            return;
+        }
+        if self.unsaved.save_to_fallback(unsaved) {
+            println!("Last save failed, {} hits are saved to the fallback file", count);
+        } else {
+            println!("Last save failed and the fallback file can't be written, {} hits may be lost", count);
+        }
+    }
+}
+
+/// Dedicated thread owning the `Connector`: takes hits from a bounded queue and saves them every
+/// `flush_interval`, so storage latency never stalls key generation.
+pub struct ResultWriter {
+    handle: JoinHandle<()>,
+    stats: Arc<WriterStats>,
+    unsaved: Unsaved,
+}
+
+impl ResultWriter {
+    /// With `progress`, a checkpoint is written after every successful save. `fallback` opens
+    /// the file for hits the connector can't take, e.g. when the last save fails.
+    pub fn spawn(connector: Box<dyn Connector>, queue_size: usize, flush_interval: Duration,
+                 progress: Option<Arc<Progress>>, fallback: Fallback) -> (Self, HitSender) {
+        let (sender, receiver) = sync_channel(queue_size);
+        let stats = Arc::new(WriterStats::default());
+        let unsaved = Unsaved {
+            records: Arc::new(Mutex::new(vec![])),
+            fallback: Arc::new(Mutex::new(FallbackFile { open: fallback, connector: None })),
+            stats: stats.clone(),
+        };
+        let writer = Writer {
+            connector,
+            stats: stats.clone(),
+            progress,
+            unsaved: unsaved.clone(),
+            failing: false,
+        };
+        let handle = thread::spawn(move || Self::write(writer, receiver, flush_interval));
+        (Self { handle, stats: stats.clone(), unsaved }, HitSender { sender, stats })
+    }
+
+    pub fn stats(&self) -> Arc<WriterStats> {
+        self.stats.clone()
+    }
+
+    /// Handle to dump unsaved hits when the process can't wait for the writer thread.
+    pub fn unsaved(&self) -> Unsaved {
+        self.unsaved.clone()
+    }
+
+    fn write(mut writer: Writer, receiver: Receiver<Record>, flush_interval: Duration) {
+        let mut last_flush = Instant::now();
+        loop {
+            let timeout = flush_interval.checked_sub(last_flush.elapsed()).unwrap_or_default();
+            match receiver.recv_timeout(timeout) {
+                Ok(record) => writer.push(record),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if last_flush.elapsed() >= flush_interval {
+                if let Some(positions) = writer.progress.as_ref().map(|progress| progress.positions()) {
+                    // workers send hits before advancing, so every hit below these positions
+                    // is in the queue by now
+                    while let Ok(record) = receiver.try_recv() {
+                        writer.push(record);
+                    }
+                    writer.flush(Some(positions));
+                } else if !writer.unsaved.records().is_empty() {
+                    writer.flush(None);
+                }
+                last_flush = Instant::now();
+            }
+        }
+        writer.finish();
+    }
+
+    /// Waits until every `HitSender` is dropped and the rest of the queue is saved.
+    pub fn finish(self) {
+        if self.handle.join().is_err() {
+            println!("Writer thread panicked, some hits may be lost");
+        }
+        self.stats.print();
+    }
+}